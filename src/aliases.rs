@@ -0,0 +1,138 @@
+use crate::cli::{AliasCommand, OutputFormat};
+use crate::client::BlockingCanvasClient;
+use crate::error::{self, CanvasError};
+use crate::output::{self, Column, Record};
+use crate::resolve::{self, Inexact};
+use crate::state;
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// `canvas alias` names courses, saved per profile in aliases.json in its config directory.
+// An alias keeps the course id, so it still works after the course is renamed.
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Aliases {
+    aliases: BTreeMap<String, Alias>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alias {
+    pub course_id: i64,
+    // Course code and name when the alias was set, for `canvas alias list`
+    pub course: String,
+}
+
+#[derive(Debug, Serialize)]
+struct AliasEntry {
+    alias: String,
+    course_id: i64,
+    course: String,
+}
+
+impl Record for AliasEntry {
+    fn columns() -> Vec<Column> {
+        vec![
+            Column::new("alias", "Alias").alias(&["name"]),
+            Column::new("course", "Course"),
+            Column::id("course_id", "Course ID"),
+        ]
+    }
+
+    fn values(&self) -> Vec<String> {
+        vec![
+            self.alias.clone(),
+            self.course.clone(),
+            self.course_id.to_string(),
+        ]
+    }
+}
+
+impl Aliases {
+    pub fn load() -> Result<Aliases, CanvasError> {
+        let path = state::config_file("aliases.json")?;
+        match state::read_optional(&path)? {
+            Some(text) => error::decode(&path.to_string_lossy(), &text),
+            None => Ok(Aliases::default()),
+        }
+    }
+
+    fn save(&self) -> Result<(), CanvasError> {
+        state::write_json(&state::config_file("aliases.json")?, self)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Alias> {
+        self.aliases.get(name.trim())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Alias)> {
+        self.aliases.iter()
+    }
+}
+
+pub fn run(command: AliasCommand, output: OutputFormat) -> Result<(), CanvasError> {
+    match command {
+        AliasCommand::Set { name, course } => set(&crate::canvas()?, &name, &course),
+        AliasCommand::List => list(output),
+        AliasCommand::Remove { name } => remove(&name),
+    }
+}
+
+// Aliases stand in for course arguments, so they can not look like ids or contain spaces
+fn validate_alias(name: &str) -> Result<(), CanvasError> {
+    let valid = !name.is_empty()
+        && !name.chars().all(|c| c.is_ascii_digit())
+        && !name.chars().any(char::is_whitespace);
+    if valid {
+        Ok(())
+    } else {
+        Err(CanvasError::Usage(format!(
+            "Invalid alias {:?}, use a name without spaces that is not a number",
+            name
+        )))
+    }
+}
+
+fn set(canvas: &BlockingCanvasClient, name: &str, query: &str) -> Result<(), CanvasError> {
+    validate_alias(name)?;
+    let course = resolve::course(canvas, query, Inexact::Note)?;
+    let mut aliases = Aliases::load()?;
+    let label = format!("{} {}", course.course_code, course.name);
+    println!("{} {} -> {}", "Alias".green(), name, label);
+    aliases.aliases.insert(
+        name.to_string(),
+        Alias {
+            course_id: course.id,
+            course: label,
+        },
+    );
+    aliases.save()
+}
+
+fn list(output: OutputFormat) -> Result<(), CanvasError> {
+    let entries: Vec<AliasEntry> = Aliases::load()?
+        .aliases
+        .into_iter()
+        .map(|(alias, target)| AliasEntry {
+            alias,
+            course_id: target.course_id,
+            course: target.course,
+        })
+        .collect();
+    if entries.is_empty() && output == OutputFormat::Table {
+        println!("No aliases yet, add one with `canvas alias set <name> <course>`");
+        return Ok(());
+    }
+    output::render(&entries, output)
+}
+
+fn remove(name: &str) -> Result<(), CanvasError> {
+    let mut aliases = Aliases::load()?;
+    if aliases.aliases.remove(name).is_none() {
+        return Err(CanvasError::NotFound(format!("Alias {} not found", name)));
+    }
+    aliases.save()?;
+    println!("{} {}", "Removed alias".green(), name);
+    Ok(())
+}
@@ -0,0 +1,180 @@
+use crate::error::CanvasError;
+use crate::state;
+use chrono::{Local, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+// GET responses are kept in responses/<sha256 of the url>.json in the profile's cache directory.
+// Within the TTL they are used as they are, after it they are revalidated with If-None-Match /
+// If-Modified-Since so an unchanged listing costs Canvas a 304 instead of the whole body.
+
+pub const DEFAULT_TTL_SECS: u64 = 300;
+
+// Set once by run() from --offline and --cache-ttl
+static SETTINGS: OnceLock<CacheSettings> = OnceLock::new();
+// The stale data notice is printed once per command, not once per page
+static NOTICE_SHOWN: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy)]
+pub struct CacheSettings {
+    pub offline: bool,
+    pub ttl_secs: u64,
+}
+
+impl Default for CacheSettings {
+    fn default() -> CacheSettings {
+        CacheSettings {
+            offline: false,
+            ttl_secs: DEFAULT_TTL_SECS,
+        }
+    }
+}
+
+pub fn configure(settings: CacheSettings) {
+    SETTINGS.set(settings).ok();
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    // rel="next" link of a paginated response, so pages can be followed offline too
+    pub next: Option<String>,
+    // Unix timestamp of when Canvas last sent or confirmed this body
+    pub fetched_at: i64,
+    pub body: String,
+}
+
+pub struct ResponseCache {
+    dir: PathBuf,
+    settings: CacheSettings,
+}
+
+impl ResponseCache {
+    pub fn for_profile() -> Result<ResponseCache, CanvasError> {
+        Ok(ResponseCache {
+            dir: state::cache_file("responses")?,
+            settings: SETTINGS.get().copied().unwrap_or_default(),
+        })
+    }
+
+    #[cfg(test)]
+    pub fn in_dir(dir: PathBuf, settings: CacheSettings) -> ResponseCache {
+        ResponseCache { dir, settings }
+    }
+
+    pub fn offline(&self) -> bool {
+        self.settings.offline
+    }
+
+    fn path(&self, url: &str) -> PathBuf {
+        let hash: String = Sha256::digest(url.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        self.dir.join(format!("{}.json", hash))
+    }
+
+    // A missing or unreadable entry is a cache miss, it is replaced by the next response
+    pub fn load(&self, url: &str) -> Option<CachedResponse> {
+        let text = state::read_optional(&self.path(url)).ok()??;
+        serde_json::from_str::<CachedResponse>(&text)
+            .ok()
+            .filter(|cached| cached.url == url)
+    }
+
+    // Failing to cache a response never fails the command that fetched it
+    pub fn store(&self, cached: &CachedResponse) {
+        state::write_json(&self.path(&cached.url), cached).ok();
+    }
+
+    // Drops every response whose url starts with `prefix`, like a failed store a failed removal is
+    // not an error
+    pub fn forget(&self, prefix: &str) {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for path in entries.filter_map(Result::ok).map(|entry| entry.path()) {
+            let cached = state::read_optional(&path)
+                .ok()
+                .flatten()
+                .and_then(|text| serde_json::from_str::<CachedResponse>(&text).ok());
+            if cached.is_some_and(|cached| cached.url.starts_with(prefix)) {
+                std::fs::remove_file(&path).ok();
+            }
+        }
+    }
+
+    pub fn is_fresh(&self, cached: &CachedResponse) -> bool {
+        let age = Utc::now().timestamp() - cached.fetched_at;
+        (0..self.settings.ttl_secs as i64).contains(&age)
+    }
+}
+
+pub fn not_cached(url: &str) -> CanvasError {
+    CanvasError::Config(format!(
+        "Nothing cached for {} yet, run the command once without --offline",
+        url
+    ))
+}
+
+// Tells the user the listing comes from the cache when offline, with how old it is
+pub fn notice_stale(cached: &CachedResponse) {
+    if NOTICE_SHOWN.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    let fetched_at = match Local.timestamp_opt(cached.fetched_at, 0).single() {
+        Some(fetched_at) => fetched_at,
+        None => return,
+    };
+    let age = Local::now().signed_duration_since(fetched_at);
+    let age = if age.num_days() > 0 {
+        format!("{} days ago", age.num_days())
+    } else if age.num_hours() > 0 {
+        format!("{} hours ago", age.num_hours())
+    } else {
+        format!("{} minutes ago", age.num_minutes())
+    };
+    eprintln!(
+        "Offline, showing data stale as of {} ({})",
+        fetched_at.format("%Y-%m-%d %H:%M"),
+        age
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forgets_responses_by_url_prefix() {
+        let dir =
+            std::env::temp_dir().join(format!("canvas-cli-test-forget-{}", std::process::id()));
+        let cache = ResponseCache::in_dir(dir.clone(), CacheSettings::default());
+        let urls = [
+            "https://canvas.test/api/v1/courses/3/assignments?include[]=submission",
+            "https://canvas.test/api/v1/courses/3/assignments/7",
+            "https://canvas.test/api/v1/courses/31/assignments",
+            "https://canvas.test/api/v1/courses",
+        ];
+        for url in urls {
+            cache.store(&CachedResponse {
+                url: url.to_string(),
+                etag: None,
+                last_modified: None,
+                next: None,
+                fetched_at: Utc::now().timestamp(),
+                body: "[]".to_string(),
+            });
+        }
+        cache.forget("https://canvas.test/api/v1/courses/3/assignments");
+        let kept: Vec<bool> = urls.iter().map(|url| cache.load(url).is_some()).collect();
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(kept, vec![false, false, true, true]);
+    }
+}
@@ -0,0 +1,304 @@
+use crate::cache;
+use crate::completions::{
+    alias_candidates, assignment_candidates, course_candidates, profile_candidates,
+};
+use crate::dates::{self, Zone};
+use crate::filters;
+use chrono::{DateTime, Utc};
+use clap::builder::RangedU64ValueParser;
+use clap::{Args, Parser, Subcommand, ValueEnum, ValueHint};
+use clap_complete::ArgValueCandidates;
+use regex::Regex;
+use std::path::PathBuf;
+
+// Command line definition for canvas, the help output for every command is generated from these docs
+
+/// Interact with your Canvas account from the command line.
+///
+/// Submitting works like git: `add` a file, `commit` it to Canvas and `submit` it to an assignment.
+#[derive(Debug, Parser)]
+#[command(name = "canvas", version)]
+pub struct Cli {
+    /// Use the credentials saved for this profile instead of the default one, see `canvas profile`
+    #[arg(long, global = true, env = "CANVAS_PROFILE")]
+    pub profile: Option<String>,
+
+    /// How listings are printed
+    #[arg(long, short, global = true, value_enum, default_value_t = OutputFormat::Table)]
+    pub output: OutputFormat,
+
+    /// When to color the table, `auto` leaves it out when piped or when NO_COLOR is set
+    #[arg(long, global = true, value_enum, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+
+    /// Columns to show and their order, e.g. `--columns name,due`
+    #[arg(long, global = true, value_delimiter = ',')]
+    pub columns: Option<Vec<String>>,
+
+    /// Time zone dates are shown in: a name like America/Chicago, `local` or `course` (each course's own)
+    #[arg(long, global = true, env = "CANVAS_TIMEZONE", value_name = "ZONE", value_parser = dates::parse_zone)]
+    pub timezone: Option<Zone>,
+
+    /// How dates are shown
+    #[arg(long, global = true, value_enum, env = "CANVAS_DATE_FORMAT", default_value_t = DateFormat::Locale)]
+    pub date_format: DateFormat,
+
+    /// Print every request sent to Canvas
+    #[arg(long, short, global = true)]
+    pub verbose: bool,
+
+    /// Show courses and assignments from the cache instead of asking Canvas
+    #[arg(long, global = true)]
+    pub offline: bool,
+
+    /// Seconds a cached response is used before asking Canvas whether it changed
+    #[arg(long, global = true, env = "CANVAS_CACHE_TTL", default_value_t = cache::DEFAULT_TTL_SECS)]
+    pub cache_ttl: u64,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Get account information
+    Account,
+    /// Get courses
+    Courses,
+    /// Get assignments for a course, or for every course when none is given
+    Assignments {
+        /// Course to list assignments for: its id, code, part of its name or an alias
+        #[arg(value_name = "COURSE", add = ArgValueCandidates::new(course_candidates))]
+        course: Option<String>,
+        /// How many courses to fetch at the same time when listing every course
+        #[arg(long, short, default_value_t = 4, value_parser = RangedU64ValueParser::<usize>::new().range(1..=32))]
+        jobs: usize,
+        #[command(flatten)]
+        filter: AssignmentFilter,
+    },
+    /// List what is due soon across every course: assignments, quizzes and discussions
+    Todo {
+        /// How many days ahead to look
+        #[arg(long, default_value_t = 7, value_parser = RangedU64ValueParser::<u32>::new().range(1..=365))]
+        days: u32,
+    },
+    /// Login to your account
+    Login {
+        /// Sign in through the browser with Canvas OAuth2 instead of pasting an access token
+        #[arg(long)]
+        oauth: bool,
+        /// Client id of the Canvas developer key used with --oauth
+        #[arg(long, env = "CANVAS_CLIENT_ID")]
+        client_id: Option<String>,
+        /// Client secret of the Canvas developer key used with --oauth
+        #[arg(long, env = "CANVAS_CLIENT_SECRET", hide_env_values = true)]
+        client_secret: Option<String>,
+        /// Port Canvas redirects back to on localhost with --oauth, 0 picks a free one
+        #[arg(long, default_value_t = 0)]
+        redirect_port: u16,
+    },
+    /// Add files to be submitted
+    Add {
+        /// Files to upload
+        #[arg(required = true, value_hint = ValueHint::FilePath)]
+        file_paths: Vec<String>,
+    },
+    /// Upload the added files to Canvas
+    Commit {
+        /// Comment for the grader, sent with the submission; without it $EDITOR is opened
+        #[arg(long, short)]
+        message: Option<String>,
+    },
+    /// List the added and committed files
+    Status,
+    /// Unstage files, or every file when none are given
+    Reset {
+        /// Files to unstage
+        #[arg(value_hint = ValueHint::FilePath)]
+        file_paths: Vec<String>,
+    },
+    /// Submit the committed files to an assignment
+    Submit {
+        /// Course the assignment belongs to: its id, code, part of its name or an alias
+        #[arg(value_name = "COURSE", required_unless_present = "interactive", add = ArgValueCandidates::new(course_candidates))]
+        course: Option<String>,
+        /// Assignment to submit to: its id or part of its name, see `canvas assignments <course>`
+        #[arg(value_name = "ASSIGNMENT", required_unless_present = "interactive", add = ArgValueCandidates::new(assignment_candidates))]
+        assignment: Option<String>,
+        /// Pick the course, assignment and files from lists, then add, commit and submit them
+        #[arg(long, short)]
+        interactive: bool,
+        /// Submit even when the assignment looks like it would not accept the files
+        #[arg(long)]
+        force: bool,
+    },
+    /// Add, commit and submit files in one go
+    Push {
+        /// Course the assignment belongs to: its id, code, part of its name or an alias
+        #[arg(value_name = "COURSE", add = ArgValueCandidates::new(course_candidates))]
+        course: String,
+        /// Assignment to submit to: its id or part of its name
+        #[arg(value_name = "ASSIGNMENT", add = ArgValueCandidates::new(assignment_candidates))]
+        assignment: String,
+        /// Files to submit
+        #[arg(required = true, value_hint = ValueHint::FilePath)]
+        file_paths: Vec<String>,
+        /// Comment for the grader, sent with the submission
+        #[arg(long, short)]
+        message: Option<String>,
+        /// Submit even when the assignment looks like it would not accept the files
+        #[arg(long)]
+        force: bool,
+    },
+    /// Move the credentials and staged files an older version left in this directory into the profile
+    Migrate,
+    /// Give courses short names to use instead of their id, e.g. `canvas alias set os CS-392`
+    Alias {
+        #[command(subcommand)]
+        command: AliasCommand,
+    },
+    /// Manage the profiles for different Canvas schools or accounts
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommand,
+    },
+    /// Print the shell completion script, e.g. `source <(canvas completions bash)`
+    Completions {
+        /// Shell to print the script for
+        #[arg(value_enum)]
+        shell: CompletionShell,
+    },
+    /// Print the man page
+    Manpage {
+        /// Write a man page for every command into this directory instead
+        #[arg(long)]
+        dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ProfileCommand {
+    /// List the profiles, the default one is marked with *
+    List,
+    /// Use this profile when --profile is not given
+    Use {
+        /// Profile to use by default
+        #[arg(add = ArgValueCandidates::new(profile_candidates))]
+        name: String,
+    },
+    /// Show or set the time zone dates are shown in for this profile
+    Timezone {
+        /// A name like America/Chicago, `local` or `course` (each course's own)
+        zone: Option<String>,
+    },
+    /// Delete a profile with its token, staged files and cache
+    Remove {
+        /// Profile to delete
+        #[arg(add = ArgValueCandidates::new(profile_candidates))]
+        name: String,
+        /// Do not ask for confirmation
+        #[arg(long, short)]
+        yes: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AliasCommand {
+    /// Name a course, the alias works wherever a course is expected
+    Set {
+        /// Short name to use, e.g. os
+        name: String,
+        /// Course it stands for: its id, code or part of its name
+        #[arg(value_name = "COURSE", add = ArgValueCandidates::new(course_candidates))]
+        course: String,
+    },
+    /// List the aliases of this profile
+    List,
+    /// Forget an alias
+    Remove {
+        /// Alias to forget
+        #[arg(add = ArgValueCandidates::new(alias_candidates))]
+        name: String,
+    },
+}
+
+#[derive(Debug, Default, Args)]
+pub struct AssignmentFilter {
+    /// Only assignments due in the future
+    #[arg(long)]
+    pub upcoming: bool,
+    /// Only assignments past their due date that were not submitted
+    #[arg(long)]
+    pub overdue: bool,
+    /// Only assignments that expect a submission and were not submitted
+    #[arg(long)]
+    pub unsubmitted: bool,
+    /// Only assignments due before this date, e.g. 2026-05-01, tomorrow or +7d
+    #[arg(long, value_name = "DATE", value_parser = filters::parse_date)]
+    pub due_before: Option<DateTime<Utc>>,
+    /// Only assignments due after this date, e.g. 2026-05-01, today or +1d
+    #[arg(long, value_name = "DATE", value_parser = filters::parse_date)]
+    pub due_after: Option<DateTime<Utc>>,
+    /// Only assignments accepting this submission type, e.g. online_upload
+    #[arg(long = "type", value_name = "TYPE")]
+    pub kind: Option<String>,
+    /// Only assignments whose name matches this regular expression (ignoring case)
+    #[arg(long, value_name = "REGEX", value_parser = filters::parse_regex)]
+    pub search: Option<Regex>,
+    /// Order of the listing
+    #[arg(long, value_enum, default_value_t = AssignmentSort::Due)]
+    pub sort: AssignmentSort,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AssignmentSort {
+    /// Soonest due first, undated last
+    #[default]
+    Due,
+    /// Alphabetically
+    Name,
+    /// Most points first
+    Points,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DateFormat {
+    /// Weekday, date and time as written in the language of LC_TIME or LANG
+    Locale,
+    /// RFC 3339, e.g. 2026-05-01T23:59:00-05:00
+    Iso,
+    /// How long from now, e.g. "in 3h" or "2 days ago"
+    Relative,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Colored columns for reading in the terminal
+    Table,
+    /// Pretty printed JSON
+    Json,
+    /// One JSON object per line
+    Ndjson,
+    /// Comma separated values with a header row
+    Csv,
+    /// Tab separated values with a header row
+    Tsv,
+    /// YAML
+    Yaml,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+    Elvish,
+}
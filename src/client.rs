@@ -0,0 +1,1186 @@
+use crate::cache::{self, CachedResponse, ResponseCache};
+use crate::credentials::Credentials;
+use crate::error::{self, CanvasError};
+use crate::oauth;
+use chrono::Utc;
+use futures::stream::{self, StreamExt};
+use rand::Rng;
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+    LAST_MODIFIED, LINK, RETRY_AFTER,
+};
+use reqwest::{StatusCode, Url};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Sent with every request so school admins can tell where the traffic comes from
+const USER_AGENT: &str = concat!("canvas-cli/", env!("CARGO_PKG_VERSION"));
+
+// Set by --verbose, prints each request and the status Canvas answered with
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_verbose(verbose: bool) {
+    VERBOSE.store(verbose, Ordering::Relaxed);
+}
+
+// A stalled connection fails (and is retried when that is safe) instead of hanging the command
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+// Multipart posts carry whole files, which take longer on a slow connection
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(300);
+
+// The reqwest client every request to Canvas goes through, it keeps connections open between requests
+pub fn http_client() -> Result<reqwest::Client, CanvasError> {
+    Ok(reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()?)
+}
+
+// Canvas gives every token a bucket of request cost that refills over time and answers
+// 403 "Rate Limit Exceeded" once it is empty, see
+// https://canvas.instructure.com/doc/api/file.throttling.html
+const RATE_LIMIT_REMAINING: &str = "X-Rate-Limit-Remaining";
+const REQUEST_COST: &str = "X-Request-Cost";
+// Below this much quota left requests are spaced out, more the closer the bucket gets to empty
+const LOW_QUOTA: f64 = 200.0;
+const MAX_THROTTLE_DELAY: Duration = Duration::from_secs(2);
+
+/*
+struct: RetryPolicy
+Description: How often and how long CanvasClient waits before sending a failed request again.
+    The delay doubles after every attempt up to max_delay, with random jitter so concurrent
+    requests do not all come back at the same moment. A Retry-After header from Canvas wins, up to
+    max_delay too.
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 4,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(16),
+        }
+    }
+}
+
+impl RetryPolicy {
+    // Retry-After when Canvas sent one, never longer than max_delay so a command can not stall
+    // for as long as the server asks
+    fn delay_after(&self, retry_after: Option<Duration>, attempt: u32) -> Duration {
+        match retry_after {
+            Some(retry_after) => retry_after.min(self.max_delay),
+            None => self.delay(attempt),
+        }
+    }
+
+    fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+// Whether sending a request twice does no harm. GETs and file uploads are (a repeated upload at most
+// leaves an unused file behind), a submission is only sent again when Canvas certainly did not act
+// on it: it answered 403 Rate Limit Exceeded or the connection never opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Idempotency {
+    Idempotent,
+    NotIdempotent,
+}
+
+// Where the bearer token comes from
+enum Auth {
+    // Saved by `canvas login` for the profile, OAuth tokens are refreshed when they expire
+    Profile(Credentials),
+    // Given directly, e.g. by login to check a token before saving it
+    Token(String),
+}
+
+/*
+struct: CanvasClient
+Description: Talks to one Canvas instance: joins api paths onto the school url, authorizes requests,
+    follows pagination and turns error responses into CanvasError. Cheap to share by reference
+    across concurrent requests, see BlockingCanvasClient for calling it from synchronous code.
+ */
+pub struct CanvasClient {
+    base_url: String,
+    auth: Auth,
+    http: reqwest::Client,
+    retry: RetryPolicy,
+    // GET responses of the profile, None for clients that are not tied to a saved profile
+    cache: Option<ResponseCache>,
+    // X-Rate-Limit-Remaining of the last response, shared by every request made with this client
+    quota_remaining: Mutex<Option<f64>>,
+}
+
+impl CanvasClient {
+    // Client for the current profile's saved school url and token
+    pub fn from_profile() -> Result<CanvasClient, CanvasError> {
+        let credentials = Credentials::load()?;
+        let base_url = credentials.school_base_url()?.to_string();
+        let mut client = CanvasClient::new(base_url, Auth::Profile(credentials))?;
+        client.cache = Some(ResponseCache::for_profile()?);
+        Ok(client)
+    }
+
+    pub fn with_token(base_url: &str, token: &str) -> Result<CanvasClient, CanvasError> {
+        CanvasClient::new(base_url.to_string(), Auth::Token(token.to_string()))
+    }
+
+    fn new(base_url: String, auth: Auth) -> Result<CanvasClient, CanvasError> {
+        Ok(CanvasClient {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            auth,
+            http: http_client()?,
+            retry: RetryPolicy::default(),
+            cache: None,
+            quota_remaining: Mutex::new(None),
+        })
+    }
+
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> CanvasClient {
+        self.retry = retry;
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    // Api paths like "/api/v1/courses" are relative to the school url, full urls (pagination links,
+    // upload urls) are used as they are
+    pub(crate) fn url(&self, path: &str) -> String {
+        if path.starts_with("http://") || path.starts_with("https://") {
+            path.to_string()
+        } else {
+            format!("{}{}", self.base_url, path)
+        }
+    }
+
+    // The token is only sent to the school's own Canvas, never to e.g. the storage an upload url points at
+    fn is_canvas_url(&self, url: &str) -> bool {
+        match (Url::parse(url), Url::parse(&self.base_url)) {
+            (Ok(url), Ok(base)) => url.origin() == base.origin(),
+            _ => false,
+        }
+    }
+
+    // Builds the bearer header, refreshing an expired OAuth access token first
+    // (or any OAuth access token when `refresh` is set)
+    async fn auth_headers(&self, refresh: bool) -> Result<HeaderMap, CanvasError> {
+        let token = match &self.auth {
+            Auth::Token(token) => token.clone(),
+            Auth::Profile(credentials) => match credentials.oauth_token()? {
+                Some(token) if refresh || token.is_expired() => {
+                    oauth::refresh(&self.http, credentials, &token)
+                        .await?
+                        .access_token
+                }
+                Some(token) => token.access_token,
+                None => credentials.auth_token()?,
+            },
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            format!("Bearer {}", token)
+                .parse()
+                .map_err(|_| CanvasError::Config("Canvas auth token is not valid".to_string()))?,
+        );
+        Ok(headers)
+    }
+
+    fn uses_oauth(&self) -> bool {
+        matches!(&self.auth, Auth::Profile(credentials) if credentials.uses_oauth())
+    }
+
+    /*
+    function: CanvasClient::send
+    Description: Sends the request made by `build`, with the auth headers when it goes to Canvas.
+        - 401 with an OAuth profile: the token is refreshed and the request sent again
+        - 403 Rate Limit Exceeded or no connection: sent again after a backoff
+        - 429, 5xx and timeouts: sent again after a backoff when the request is idempotent
+        Other 403s come back as an error, every other response is returned as it is.
+     */
+    async fn send<F>(
+        &self,
+        url: &str,
+        idempotency: Idempotency,
+        build: F,
+    ) -> Result<reqwest::Response, CanvasError>
+    where
+        F: Fn(HeaderMap) -> reqwest::RequestBuilder,
+    {
+        if self.cache.as_ref().is_some_and(ResponseCache::offline) {
+            return Err(CanvasError::Usage(
+                "This command needs Canvas, run it without --offline".to_string(),
+            ));
+        }
+        let to_canvas = self.is_canvas_url(url);
+        let mut refresh = false;
+        let mut attempt = 0;
+        loop {
+            let headers = if to_canvas {
+                self.throttle().await;
+                self.auth_headers(refresh).await?
+            } else {
+                HeaderMap::new()
+            };
+            let can_retry = attempt < self.retry.max_retries;
+
+            let resp = match build(headers).send().await {
+                Ok(resp) => resp,
+                Err(err) => {
+                    let retry = err.is_connect()
+                        || (err.is_timeout() && idempotency == Idempotency::Idempotent);
+                    if !(retry && can_retry) {
+                        return Err(err.into());
+                    }
+                    let delay = self.retry.delay(attempt);
+                    log_retry(&err.to_string(), delay);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                    continue;
+                }
+            };
+            if to_canvas {
+                self.record_quota(resp.headers());
+            }
+
+            let status = resp.status();
+            if status == StatusCode::UNAUTHORIZED && self.uses_oauth() && !refresh {
+                log_response(&resp);
+                refresh = true;
+                continue;
+            }
+            refresh = false;
+            let retry = match status {
+                StatusCode::FORBIDDEN => {
+                    let resp_url = resp.url().to_string();
+                    let retry_after = retry_after(resp.headers());
+                    log_response(&resp);
+                    let body = resp.text().await?;
+                    if !(is_rate_limited(&body) && can_retry) {
+                        return Err(CanvasError::from_response(status, &resp_url, &body));
+                    }
+                    let delay = self.retry.delay_after(retry_after, attempt);
+                    log_retry("Canvas rate limit exceeded", delay);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                    continue;
+                }
+                // A proxy in front of Canvas may answer these after Canvas acted on the request
+                status if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() => {
+                    idempotency == Idempotency::Idempotent
+                }
+                _ => false,
+            };
+            if !(retry && can_retry) {
+                return Ok(resp);
+            }
+            log_response(&resp);
+            let delay = self.retry.delay_after(retry_after(resp.headers()), attempt);
+            log_retry(status.as_str(), delay);
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    // Waits before a request when the last response said the rate limit bucket is nearly empty
+    async fn throttle(&self) {
+        let remaining = *self.quota_remaining.lock().unwrap();
+        if let Some(remaining) = remaining.filter(|remaining| *remaining < LOW_QUOTA) {
+            let pressure = (LOW_QUOTA - remaining.max(0.0)) / LOW_QUOTA;
+            tokio::time::sleep(MAX_THROTTLE_DELAY.mul_f64(pressure)).await;
+        }
+    }
+
+    fn record_quota(&self, headers: &HeaderMap) {
+        if let Some(remaining) = header_f64(headers, RATE_LIMIT_REMAINING) {
+            *self.quota_remaining.lock().unwrap() = Some(remaining);
+        }
+    }
+
+    /*
+    function: CanvasClient::get_cached
+    Description: GETs `url` through the response cache: a fresh cached body is used as it is, a
+        stale one is revalidated with its ETag / Last-Modified and offline the cached body is used
+        whatever its age.
+    Return: the body and the rel="next" link of the response
+     */
+    async fn get_cached(&self, url: &str) -> Result<(String, Option<String>), CanvasError> {
+        let cached = self.cache.as_ref().and_then(|cache| cache.load(url));
+        if let Some(cache) = &self.cache {
+            if cache.offline() {
+                let cached = cached.ok_or_else(|| cache::not_cached(url))?;
+                cache::notice_stale(&cached);
+                return Ok((cached.body, cached.next));
+            }
+            if let Some(cached) = cached.as_ref().filter(|cached| cache.is_fresh(cached)) {
+                return Ok((cached.body.clone(), cached.next.clone()));
+            }
+        }
+
+        let resp = self
+            .send(url, Idempotency::Idempotent, |mut headers| {
+                if let Some(cached) = &cached {
+                    insert_header(&mut headers, IF_NONE_MATCH, cached.etag.as_deref());
+                    insert_header(
+                        &mut headers,
+                        IF_MODIFIED_SINCE,
+                        cached.last_modified.as_deref(),
+                    );
+                }
+                self.http.get(url).headers(headers)
+            })
+            .await?;
+        if let (StatusCode::NOT_MODIFIED, Some(mut cached)) = (resp.status(), cached) {
+            log_response(&resp);
+            cached.fetched_at = Utc::now().timestamp();
+            if let Some(cache) = &self.cache {
+                cache.store(&cached);
+            }
+            return Ok((cached.body, cached.next));
+        }
+
+        let next = next_page_url(resp.headers());
+        let etag = header_string(resp.headers(), ETAG);
+        let last_modified = header_string(resp.headers(), LAST_MODIFIED);
+        let body = read_body(resp).await?;
+        if let Some(cache) = &self.cache {
+            cache.store(&CachedResponse {
+                url: url.to_string(),
+                etag,
+                last_modified,
+                next: next.clone(),
+                fetched_at: Utc::now().timestamp(),
+                body: body.clone(),
+            });
+        }
+        Ok((body, next))
+    }
+
+    pub async fn get<T>(&self, path: &str) -> Result<T, CanvasError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let url = self.url(path);
+        let (body, _) = self.get_cached(&url).await?;
+        error::decode(&url, &body)
+    }
+
+    // A GET that never uses the cache, for checks that must see what Canvas has right now
+    pub async fn get_uncached<T>(&self, path: &str) -> Result<T, CanvasError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let url = self.url(path);
+        let resp = self
+            .send(&url, Idempotency::Idempotent, |headers| {
+                self.http.get(&url).headers(headers)
+            })
+            .await?;
+        read_json(resp).await
+    }
+
+    // Drops the cached responses of every path starting with `path`, after a change on Canvas
+    // made them wrong
+    pub fn forget(&self, path: &str) {
+        if let Some(cache) = &self.cache {
+            cache.forget(&self.url(path));
+        }
+    }
+
+    /*
+    function: CanvasClient::get_paginated
+    Description: Fetches a Canvas list endpoint and follows the `Link: <...>; rel="next"` headers
+                 until the last page, collecting every page into a single Vec
+    Parameters: path -> first page, max_pages -> optional cap on the number of pages requested
+    Return: Result<Vec<T>, CanvasError>
+     */
+    pub async fn get_paginated<T>(
+        &self,
+        path: &str,
+        max_pages: Option<usize>,
+    ) -> Result<Vec<T>, CanvasError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let mut items: Vec<T> = Vec::new();
+        let mut next_url = Some(self.url(path));
+        let mut pages_fetched = 0;
+
+        while let Some(url) = next_url {
+            if max_pages.is_some_and(|max| pages_fetched >= max) {
+                break;
+            }
+            let (body, next) = self.get_cached(&url).await?;
+            next_url = next;
+            let page: Vec<T> = error::decode(&url, &body)?;
+            items.extend(page);
+            pages_fetched += 1;
+        }
+        Ok(items)
+    }
+
+    /*
+    function: CanvasClient::post_form
+    Description: Posts a multipart form. The form is made by `form` every time the request is sent,
+                 since a multipart body can only be sent once and the request may be repeated
+     */
+    pub async fn post_form<T, F>(
+        &self,
+        path: &str,
+        idempotency: Idempotency,
+        form: F,
+    ) -> Result<T, CanvasError>
+    where
+        T: serde::de::DeserializeOwned,
+        F: Fn() -> reqwest::multipart::Form,
+    {
+        let url = self.url(path);
+        let resp = self
+            .send(&url, idempotency, |headers| {
+                self.http
+                    .post(&url)
+                    .headers(headers)
+                    .timeout(UPLOAD_TIMEOUT)
+                    .multipart(form())
+            })
+            .await?;
+        read_json(resp).await
+    }
+
+    /*
+    function: CanvasClient::fan_out
+    Description: Runs `fetch` for every key, e.g. every course, with at most `jobs` of them in flight
+        on this client. Results come back in the order of `keys` whatever order they finish in,
+        and a failure only fails its own key instead of the whole batch.
+     */
+    pub async fn fan_out<'a, K, T, F, Fut>(
+        &'a self,
+        keys: Vec<K>,
+        jobs: usize,
+        fetch: F,
+    ) -> Vec<(K, Result<T, CanvasError>)>
+    where
+        K: Clone,
+        F: Fn(&'a CanvasClient, K) -> Fut,
+        Fut: Future<Output = Result<T, CanvasError>> + 'a,
+    {
+        stream::iter(keys)
+            .map(|key| {
+                let fetched = fetch(self, key.clone());
+                async move { (key, fetched.await) }
+            })
+            .buffered(jobs.max(1))
+            .collect()
+            .await
+    }
+}
+
+/*
+struct: BlockingCanvasClient
+Description: Runs CanvasClient on its own runtime for synchronous callers like the commands in lib.rs.
+    Every call goes through the same client, so a command making several requests reuses connections.
+ */
+pub struct BlockingCanvasClient {
+    client: CanvasClient,
+    runtime: tokio::runtime::Runtime,
+}
+
+impl BlockingCanvasClient {
+    pub fn new(client: CanvasClient) -> Result<BlockingCanvasClient, CanvasError> {
+        Ok(BlockingCanvasClient {
+            client,
+            runtime: runtime()?,
+        })
+    }
+
+    pub fn from_profile() -> Result<BlockingCanvasClient, CanvasError> {
+        BlockingCanvasClient::new(CanvasClient::from_profile()?)
+    }
+
+    // The async client underneath, for running several requests at once with block_on
+    pub fn client(&self) -> &CanvasClient {
+        &self.client
+    }
+
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    pub fn get<T>(&self, path: &str) -> Result<T, CanvasError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        self.runtime.block_on(self.client.get(path))
+    }
+
+    pub fn get_uncached<T>(&self, path: &str) -> Result<T, CanvasError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        self.runtime.block_on(self.client.get_uncached(path))
+    }
+
+    pub fn forget(&self, path: &str) {
+        self.client.forget(path)
+    }
+
+    pub fn get_paginated<T>(
+        &self,
+        path: &str,
+        max_pages: Option<usize>,
+    ) -> Result<Vec<T>, CanvasError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        self.runtime
+            .block_on(self.client.get_paginated(path, max_pages))
+    }
+
+    pub fn post_form<T, F>(
+        &self,
+        path: &str,
+        idempotency: Idempotency,
+        form: F,
+    ) -> Result<T, CanvasError>
+    where
+        T: serde::de::DeserializeOwned,
+        F: Fn() -> reqwest::multipart::Form,
+    {
+        self.runtime
+            .block_on(self.client.post_form(path, idempotency, form))
+    }
+}
+
+// A runtime on the current thread, for running async code from synchronous code
+pub fn runtime() -> Result<tokio::runtime::Runtime, CanvasError> {
+    Ok(tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?)
+}
+
+pub fn block_on<F: Future>(future: F) -> Result<F::Output, CanvasError> {
+    Ok(runtime()?.block_on(future))
+}
+
+fn log_response(resp: &reqwest::Response) {
+    if !VERBOSE.load(Ordering::Relaxed) {
+        return;
+    }
+    let headers = resp.headers();
+    match (
+        header_f64(headers, REQUEST_COST),
+        header_f64(headers, RATE_LIMIT_REMAINING),
+    ) {
+        (Some(cost), Some(remaining)) => eprintln!(
+            "{} {} (cost {}, {} left)",
+            resp.status(),
+            resp.url(),
+            cost,
+            remaining
+        ),
+        _ => eprintln!("{} {}", resp.status(), resp.url()),
+    }
+}
+
+fn log_retry(reason: &str, delay: Duration) {
+    if VERBOSE.load(Ordering::Relaxed) {
+        eprintln!("{}, retrying in {}ms", reason, delay.as_millis());
+    }
+}
+
+fn header_f64(headers: &HeaderMap, name: &str) -> Option<f64> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+// Retry-After in seconds, Canvas does not send the http date form
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let seconds: f64 = headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+// Canvas uses 403 for both permissions and throttling, only the body tells them apart
+fn is_rate_limited(body: &str) -> bool {
+    body.contains("Rate Limit Exceeded")
+}
+
+// Turns a response into T, or into the matching CanvasError when Canvas did not return 2xx
+async fn read_json<T>(resp: reqwest::Response) -> Result<T, CanvasError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let url = resp.url().to_string();
+    let body = read_body(resp).await?;
+    error::decode(&url, &body)
+}
+
+async fn read_body(resp: reqwest::Response) -> Result<String, CanvasError> {
+    log_response(&resp);
+    let status = resp.status();
+    let url = resp.url().to_string();
+    let body = resp.text().await?;
+    if !status.is_success() {
+        return Err(CanvasError::from_response(status, &url, &body));
+    }
+    Ok(body)
+}
+
+fn header_string(headers: &HeaderMap, name: HeaderName) -> Option<String> {
+    Some(headers.get(name)?.to_str().ok()?.to_string())
+}
+
+fn insert_header(headers: &mut HeaderMap, name: HeaderName, value: Option<&str>) {
+    if let Some(value) = value.and_then(|value| HeaderValue::from_str(value).ok()) {
+        headers.insert(name, value);
+    }
+}
+
+// Pulls the rel="next" url out of an RFC 5988 Link header, if there is one
+fn next_page_url(headers: &HeaderMap) -> Option<String> {
+    let link = headers.get(LINK)?.to_str().ok()?;
+    link.split(',').find_map(|entry| {
+        let mut parts = entry.split(';');
+        let url = parts.next()?.trim();
+        let is_next = parts.any(|param| {
+            let param = param.trim();
+            param == "rel=\"next\"" || param == "rel=next"
+        });
+        if is_next && url.starts_with('<') && url.ends_with('>') {
+            Some(url[1..url.len() - 1].to_string())
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::CacheSettings;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    // A request the mock Canvas received
+    #[derive(Debug, Clone)]
+    struct Request {
+        method: String,
+        target: String,
+        // Header lines as sent, names lowercased
+        headers: Vec<(String, String)>,
+    }
+
+    impl Request {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        }
+    }
+
+    // Canvas on a local port, answering the n-th request (counting from 0) with `respond`
+    struct MockCanvas {
+        url: String,
+        requests: Arc<Mutex<Vec<Request>>>,
+    }
+
+    impl MockCanvas {
+        async fn start<F>(respond: F) -> MockCanvas
+        where
+            F: Fn(&Request, usize) -> String + Send + Sync + 'static,
+        {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let requests: Arc<Mutex<Vec<Request>>> = Arc::default();
+            let respond = Arc::new(respond);
+            let received = requests.clone();
+            tokio::spawn(async move {
+                loop {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    let requests = received.clone();
+                    let respond = respond.clone();
+                    tokio::spawn(async move {
+                        let mut reader = BufReader::new(stream);
+                        let request = read_request(&mut reader).await;
+                        let response = {
+                            let mut requests = requests.lock().unwrap();
+                            requests.push(request.clone());
+                            respond(&request, requests.len() - 1)
+                        };
+                        let stream = reader.get_mut();
+                        stream.write_all(response.as_bytes()).await.ok();
+                        stream.shutdown().await.ok();
+                    });
+                }
+            });
+            MockCanvas { url, requests }
+        }
+
+        fn client(&self) -> CanvasClient {
+            CanvasClient::with_token(&self.url, "token")
+                .unwrap()
+                .retry_policy(fast_retries())
+        }
+
+        fn requests(&self) -> Vec<Request> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    async fn read_request(reader: &mut BufReader<tokio::net::TcpStream>) -> Request {
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        let mut parts = line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let target = parts.next().unwrap_or_default().to_string();
+        let mut headers = Vec::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).await.unwrap();
+            match line.trim_end().split_once(':') {
+                Some((name, value)) => {
+                    headers.push((name.to_lowercase(), value.trim().to_string()))
+                }
+                None => break,
+            }
+        }
+        let request = Request {
+            method,
+            target,
+            headers,
+        };
+        let length: usize = request
+            .header("content-length")
+            .and_then(|length| length.parse().ok())
+            .unwrap_or(0);
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).await.unwrap();
+        request
+    }
+
+    fn response(status: &str, headers: &[(&str, &str)], body: &str) -> String {
+        let mut response = format!("HTTP/1.1 {}\r\nContent-Type: application/json\r\n", status);
+        for (name, value) in headers {
+            response.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        response.push_str(&format!(
+            "Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        ));
+        response
+    }
+
+    // Retries without the waiting, except that a Retry-After over max_delay would show up as a hang
+    fn fast_retries() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 2,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(20),
+        }
+    }
+
+    fn empty_form() -> reqwest::multipart::Form {
+        reqwest::multipart::Form::new().text("submission[submission_type]", "online_upload")
+    }
+
+    // Pages 1 to 3 of a listing, each linking to the next and to the first
+    fn page(request: &Request) -> String {
+        let url = format!("http://{}", request.header("host").unwrap_or_default());
+        let page: usize = request
+            .target
+            .rsplit("page=")
+            .next()
+            .and_then(|page| page.parse().ok())
+            .unwrap_or(1);
+        let mut links = vec![format!("<{}/api/v1/courses?page=1>; rel=\"first\"", url)];
+        if page < 3 {
+            links.insert(
+                0,
+                format!("<{}/api/v1/courses?page={}>; rel=\"next\"", url, page + 1),
+            );
+        }
+        response(
+            "200 OK",
+            &[("Link", &links.join(", "))],
+            &format!("[{}]", page),
+        )
+    }
+
+    #[tokio::test]
+    async fn follows_next_links_to_the_last_page() {
+        let canvas = MockCanvas::start(|request, _| page(request)).await;
+
+        let pages: Vec<u32> = canvas
+            .client()
+            .get_paginated("/api/v1/courses", None)
+            .await
+            .unwrap();
+        assert_eq!(pages, vec![1, 2, 3]);
+        let targets: Vec<String> = canvas.requests().into_iter().map(|r| r.target).collect();
+        assert_eq!(
+            targets,
+            vec![
+                "/api/v1/courses",
+                "/api/v1/courses?page=2",
+                "/api/v1/courses?page=3"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_page_cap() {
+        let canvas = MockCanvas::start(|request, _| page(request)).await;
+
+        let pages: Vec<u32> = canvas
+            .client()
+            .get_paginated("/api/v1/courses", Some(2))
+            .await
+            .unwrap();
+        assert_eq!(pages, vec![1, 2]);
+        assert_eq!(canvas.requests().len(), 2);
+    }
+
+    #[test]
+    fn reads_the_next_link() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            LINK,
+            HeaderValue::from_static(
+                "<https://canvas.test/a?page=1>; rel=\"current\",\
+                 <https://canvas.test/a?page=2>; rel=\"next\",\
+                 <https://canvas.test/a?page=9>; rel=\"last\"",
+            ),
+        );
+        assert_eq!(
+            next_page_url(&headers).as_deref(),
+            Some("https://canvas.test/a?page=2")
+        );
+        headers.insert(
+            LINK,
+            HeaderValue::from_static("<https://canvas.test/a?page=9>; rel=\"last\""),
+        );
+        assert_eq!(next_page_url(&headers), None);
+    }
+
+    #[tokio::test]
+    async fn retries_when_rate_limited() {
+        let canvas = MockCanvas::start(|_, n| match n {
+            0 => response(
+                "403 Forbidden",
+                &[("Retry-After", "3600")],
+                "403 Forbidden (Rate Limit Exceeded)",
+            ),
+            _ => response("200 OK", &[], "{\"id\": 1}"),
+        })
+        .await;
+        let value: serde_json::Value = canvas.client().get("/api/v1/courses/1").await.unwrap();
+        assert_eq!(value["id"], 1);
+        assert_eq!(canvas.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn fans_out_at_most_jobs_at_a_time_in_input_order() {
+        let canvas = MockCanvas::start(|request, _| match request.target.as_str() {
+            "/api/v1/courses/4" => response("500 Internal Server Error", &[], "{}"),
+            target => {
+                let id = target.trim_start_matches("/api/v1/courses/");
+                response("200 OK", &[], &format!("{{\"id\": {}}}", id))
+            }
+        })
+        .await;
+        let client = canvas.client();
+        let in_flight = AtomicUsize::new(0);
+        let most_in_flight = AtomicUsize::new(0);
+        let fetched = client
+            .fan_out((1..=6).collect(), 2, |client, course: u64| {
+                let (in_flight, most_in_flight) = (&in_flight, &most_in_flight);
+                async move {
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    most_in_flight.fetch_max(now, Ordering::SeqCst);
+                    let value: Result<serde_json::Value, CanvasError> =
+                        client.get(&format!("/api/v1/courses/{}", course)).await;
+                    // Later courses finish first
+                    tokio::time::sleep(Duration::from_millis((7 - course) * 10)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    value
+                }
+            })
+            .await;
+
+        let courses: Vec<u64> = fetched.iter().map(|(course, _)| *course).collect();
+        assert_eq!(courses, [1, 2, 3, 4, 5, 6]);
+        for (course, result) in &fetched {
+            match (course, result) {
+                (4, Err(CanvasError::Status { status, .. })) => {
+                    assert_eq!(*status, StatusCode::INTERNAL_SERVER_ERROR)
+                }
+                (course, Ok(value)) if *course != 4 => assert_eq!(value["id"], *course),
+                (course, result) => panic!("course {}: {:?}", course, result),
+            }
+        }
+        assert_eq!(most_in_flight.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_other_403s() {
+        let canvas = MockCanvas::start(|_, _| {
+            response(
+                "403 Forbidden",
+                &[],
+                "{\"errors\": [{\"message\": \"user not authorized to perform that action\"}]}",
+            )
+        })
+        .await;
+        let result: Result<serde_json::Value, CanvasError> =
+            canvas.client().get("/api/v1/courses/1").await;
+        assert!(result.is_err());
+        assert_eq!(canvas.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_while_unavailable() {
+        let canvas = MockCanvas::start(|_, n| match n {
+            0 | 1 => response("503 Service Unavailable", &[("Retry-After", "3600")], ""),
+            _ => response("200 OK", &[], "{\"id\": 1}"),
+        })
+        .await;
+        let value: serde_json::Value = canvas.client().get("/api/v1/courses/1").await.unwrap();
+        assert_eq!(value["id"], 1);
+        assert_eq!(canvas.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        let canvas = MockCanvas::start(|_, _| response("503 Service Unavailable", &[], "")).await;
+        let result: Result<serde_json::Value, CanvasError> =
+            canvas.client().get("/api/v1/courses/1").await;
+        assert!(result.is_err());
+        assert_eq!(canvas.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn does_not_resend_a_submission_after_a_server_error() {
+        let canvas =
+            MockCanvas::start(|_, _| response("500 Internal Server Error", &[], "{}")).await;
+        let result: Result<serde_json::Value, CanvasError> = canvas
+            .client()
+            .post_form(
+                "/api/v1/courses/1/assignments/2/submissions",
+                Idempotency::NotIdempotent,
+                empty_form,
+            )
+            .await;
+        assert!(result.is_err());
+        let requests = canvas.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "POST");
+    }
+
+    #[tokio::test]
+    async fn does_not_resend_a_submission_while_unavailable() {
+        let canvas = MockCanvas::start(|_, n| match n {
+            0 => response("503 Service Unavailable", &[], ""),
+            _ => response("200 OK", &[], "{\"id\": 1}"),
+        })
+        .await;
+        let result: Result<serde_json::Value, CanvasError> = canvas
+            .client()
+            .post_form(
+                "/api/v1/courses/1/assignments/2/submissions",
+                Idempotency::NotIdempotent,
+                empty_form,
+            )
+            .await;
+        assert!(result.is_err());
+        assert_eq!(canvas.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn resends_an_upload_after_a_server_error() {
+        let canvas = MockCanvas::start(|_, n| match n {
+            0 => response("500 Internal Server Error", &[], "{}"),
+            _ => response("200 OK", &[], "{\"id\": 7}"),
+        })
+        .await;
+        let value: serde_json::Value = canvas
+            .client()
+            .post_form("/upload", Idempotency::Idempotent, empty_form)
+            .await
+            .unwrap();
+        assert_eq!(value["id"], 7);
+        assert_eq!(canvas.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn revalidates_a_stale_cached_response() {
+        let canvas = MockCanvas::start(|request, _| match request.header("if-none-match") {
+            Some("\"v1\"") => response("304 Not Modified", &[("ETag", "\"v1\"")], ""),
+            _ => response("200 OK", &[("ETag", "\"v1\"")], "{\"id\": 1}"),
+        })
+        .await;
+        let dir =
+            std::env::temp_dir().join(format!("canvas-cli-test-cache-{}", std::process::id()));
+        let cache = |ttl_secs| {
+            ResponseCache::in_dir(
+                dir.clone(),
+                CacheSettings {
+                    offline: false,
+                    ttl_secs,
+                },
+            )
+        };
+
+        // Stale right away, so the second GET asks Canvas whether it changed
+        let mut client = canvas.client();
+        client.cache = Some(cache(0));
+        for _ in 0..2 {
+            let value: serde_json::Value = client.get("/api/v1/courses/1").await.unwrap();
+            assert_eq!(value["id"], 1);
+        }
+        // Fresh, so Canvas is not asked at all
+        client.cache = Some(cache(300));
+        let value: serde_json::Value = client.get("/api/v1/courses/1").await.unwrap();
+        assert_eq!(value["id"], 1);
+        std::fs::remove_dir_all(&dir).ok();
+
+        let requests = canvas.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].header("if-none-match"), None);
+        assert_eq!(requests[1].header("if-none-match"), Some("\"v1\""));
+    }
+
+    #[tokio::test]
+    async fn sends_the_token_only_to_canvas() {
+        let canvas = MockCanvas::start(|_, _| response("200 OK", &[], "{}")).await;
+        let other = MockCanvas::start(|_, _| response("200 OK", &[], "{}")).await;
+        let client = canvas.client();
+        let _: serde_json::Value = client.get("/api/v1/users/self").await.unwrap();
+        let _: serde_json::Value = client
+            .post_form(
+                &format!("{}/upload", other.url),
+                Idempotency::Idempotent,
+                empty_form,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            canvas.requests()[0].header("authorization"),
+            Some("Bearer token")
+        );
+        assert_eq!(other.requests()[0].header("authorization"), None);
+    }
+
+    #[tokio::test]
+    async fn refreshes_an_oauth_token_once_on_401() {
+        use crate::credentials::{EncryptedFileStore, StoreKind};
+        use crate::oauth::OAuthToken;
+
+        let canvas = MockCanvas::start(|request, _| {
+            match (request.method.as_str(), request.target.as_str()) {
+                ("POST", "/login/oauth2/token") => response(
+                    "200 OK",
+                    &[],
+                    "{\"access_token\": \"new\", \"expires_in\": 3600}",
+                ),
+                // Only the refreshed token works, and /revoked never does
+                (_, "/api/v1/revoked") => response("401 Unauthorized", &[], "{}"),
+                _ if request.header("authorization") == Some("Bearer new") => {
+                    response("200 OK", &[], "{\"id\": 1}")
+                }
+                _ => response("401 Unauthorized", &[], "{}"),
+            }
+        })
+        .await;
+        // The refreshed token is saved in an encrypted file in a directory of our own
+        let dir =
+            std::env::temp_dir().join(format!("canvas-cli-test-oauth-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let credentials = || {
+            Credentials {
+                school_base_url: Some(canvas.url.clone()),
+                token_store: Some(StoreKind::EncryptedFile),
+                oauth: true,
+                ..Credentials::default()
+            }
+            .with_store(EncryptedFileStore::in_dir(&dir, "test passphrase"))
+        };
+        credentials()
+            .save_oauth_token(&OAuthToken {
+                access_token: "old".to_string(),
+                refresh_token: Some("refresh".to_string()),
+                expires_at: None,
+                client_id: "client".to_string(),
+                client_secret: "secret".to_string(),
+            })
+            .unwrap();
+        let client = |credentials| {
+            CanvasClient::new(canvas.url.clone(), Auth::Profile(credentials))
+                .unwrap()
+                .retry_policy(fast_retries())
+        };
+        let methods = |requests: &[Request]| -> Vec<String> {
+            requests
+                .iter()
+                .map(|request| format!("{} {}", request.method, request.target))
+                .collect()
+        };
+
+        let value: serde_json::Value = client(credentials())
+            .get("/api/v1/users/self")
+            .await
+            .unwrap();
+        assert_eq!(value["id"], 1);
+        let requests = canvas.requests();
+        assert_eq!(
+            methods(&requests),
+            vec![
+                "GET /api/v1/users/self",
+                "POST /login/oauth2/token",
+                "GET /api/v1/users/self"
+            ]
+        );
+        assert_eq!(requests[2].header("authorization"), Some("Bearer new"));
+        let saved = credentials().oauth_token().unwrap().unwrap();
+        assert_eq!(saved.access_token, "new");
+        assert_eq!(saved.refresh_token.as_deref(), Some("refresh"));
+
+        // A 401 right after refreshing is an error, not another refresh
+        let result: Result<serde_json::Value, CanvasError> =
+            client(credentials()).get("/api/v1/revoked").await;
+        assert!(matches!(result, Err(CanvasError::Unauthorized)));
+        assert_eq!(
+            methods(&canvas.requests()[3..]),
+            vec![
+                "GET /api/v1/revoked",
+                "POST /login/oauth2/token",
+                "GET /api/v1/revoked"
+            ]
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
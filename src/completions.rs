@@ -0,0 +1,169 @@
+use crate::aliases::Aliases;
+use crate::cli::{Cli, CompletionShell};
+use crate::data::{ValidAssignment, ValidCourse};
+use crate::error::CanvasError;
+use crate::state;
+use clap::CommandFactory;
+use clap_complete::env::{Bash, Elvish, EnvCompleter, Fish, Powershell, Zsh};
+use clap_complete::CompletionCandidate;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::Path;
+
+// Courses and assignments seen by the last `courses` / `assignments` calls are kept in
+// completions.json in the profile's cache directory, so pressing TAB never has to wait on Canvas
+
+// Same variable clap_complete::CompleteEnv listens for in main()
+const COMPLETE_VAR: &str = "COMPLETE";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CompletionCache {
+    courses: Vec<Candidate>,
+    assignments: BTreeMap<i64, Vec<Candidate>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Candidate {
+    id: i64,
+    label: String,
+}
+
+fn read_cache() -> CompletionCache {
+    state::cache_file("completions.json")
+        .and_then(|path| state::read_optional(&path))
+        .ok()
+        .flatten()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+// Completions are a convenience, so failing to save them never fails the command itself
+fn write_cache(cache: &CompletionCache) {
+    if let Ok(path) = state::cache_file("completions.json") {
+        state::write_json(&path, cache).ok();
+    }
+}
+
+pub fn cache_courses(courses: &[ValidCourse]) {
+    let mut cache = read_cache();
+    cache.courses = courses
+        .iter()
+        .map(|course| Candidate {
+            id: course.id,
+            label: format!("{} {}", course.course_code, course.name),
+        })
+        .collect();
+    write_cache(&cache);
+}
+
+pub fn cache_assignments(course_id: i64, assignments: &[ValidAssignment]) {
+    let mut cache = read_cache();
+    cache.assignments.insert(
+        course_id,
+        assignments
+            .iter()
+            .map(|assignment| Candidate {
+                id: assignment.id,
+                label: assignment.name.clone(),
+            })
+            .collect(),
+    );
+    write_cache(&cache);
+}
+
+// Offers the aliases and the cached course ids, with the course code and name as the description
+pub fn course_candidates() -> Vec<CompletionCandidate> {
+    let mut candidates = alias_candidates();
+    candidates.extend(read_cache().courses.into_iter().map(|course| {
+        CompletionCandidate::new(course.id.to_string()).help(Some(course.label.into()))
+    }));
+    candidates
+}
+
+pub fn alias_candidates() -> Vec<CompletionCandidate> {
+    Aliases::load()
+        .map(|aliases| {
+            aliases
+                .iter()
+                .map(|(name, alias)| {
+                    CompletionCandidate::new(name).help(Some(alias.course.clone().into()))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn profile_candidates() -> Vec<CompletionCandidate> {
+    state::list_profiles()
+        .unwrap_or_default()
+        .into_iter()
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+// The completer does not know which course was typed before it, so offer every cached assignment
+pub fn assignment_candidates() -> Vec<CompletionCandidate> {
+    let cache = read_cache();
+    cache
+        .assignments
+        .iter()
+        .flat_map(|(course_id, assignments)| {
+            let course = cache
+                .courses
+                .iter()
+                .find(|course| course.id == *course_id)
+                .map(|course| course.label.clone())
+                .unwrap_or_else(|| course_id.to_string());
+            assignments.iter().map(move |assignment| {
+                CompletionCandidate::new(assignment.id.to_string())
+                    .help(Some(format!("{} ({})", assignment.label, course).into()))
+            })
+        })
+        .collect()
+}
+
+/*
+function: canvas completions <shell>
+Description: Prints the script that registers canvas with the shell's completion system.
+    The script calls back into canvas on every TAB so course and assignment ids stay current.
+ */
+pub fn write_completions(shell: CompletionShell) -> Result<(), CanvasError> {
+    let completer: &dyn EnvCompleter = match shell {
+        CompletionShell::Bash => &Bash,
+        CompletionShell::Zsh => &Zsh,
+        CompletionShell::Fish => &Fish,
+        CompletionShell::Powershell => &Powershell,
+        CompletionShell::Elvish => &Elvish,
+    };
+    let cmd = Cli::command();
+    let bin = cmd.get_name().to_string();
+    let exe = std::env::current_exe()?;
+
+    let mut stdout = io::stdout().lock();
+    completer.write_registration(
+        COMPLETE_VAR,
+        &bin,
+        &bin,
+        &exe.to_string_lossy(),
+        &mut stdout,
+    )?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/*
+function: canvas manpage [--dir <dir>]
+Description: Prints the roff man page for canvas, or writes one page per command into dir
+ */
+pub fn write_manpages(dir: Option<&Path>) -> Result<(), CanvasError> {
+    let cmd = Cli::command();
+    match dir {
+        Some(dir) => {
+            std::fs::create_dir_all(dir)?;
+            clap_mangen::generate_to(cmd, dir)?;
+        }
+        None => clap_mangen::Man::new(cmd).render(&mut io::stdout().lock())?,
+    }
+    Ok(())
+}
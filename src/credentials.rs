@@ -0,0 +1,399 @@
+use crate::error::{self, CanvasError};
+use crate::oauth::OAuthToken;
+use crate::state;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::Argon2;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use rpassword::read_password;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+const KEYRING_SERVICE: &str = "canvas-cli";
+
+// The secret is only read from its store once per run and updated in place when an OAuth token is
+// refreshed, together with the cached passphrase this asks for the passphrase at most once
+static SECRET: Mutex<Option<String>> = Mutex::new(None);
+static PASSPHRASE: OnceLock<String> = OnceLock::new();
+
+// Which backend holds the profile's token, saved in credentials.json next to the school url
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StoreKind {
+    Keyring,
+    EncryptedFile,
+}
+
+// A place the Canvas token can be kept without it sitting in cleartext on disk
+pub trait CredentialStore {
+    fn kind(&self) -> StoreKind;
+    fn get(&self) -> Result<Option<String>, CanvasError>;
+    fn set(&self, secret: &str) -> Result<(), CanvasError>;
+    fn delete(&self) -> Result<(), CanvasError>;
+}
+
+pub fn open_store(kind: StoreKind) -> Result<Box<dyn CredentialStore>, CanvasError> {
+    Ok(match kind {
+        StoreKind::Keyring => Box::new(KeyringStore::new()?),
+        StoreKind::EncryptedFile => Box::new(EncryptedFileStore::new()?),
+    })
+}
+
+/*
+function: save_token
+Description: Saves the token in the OS keyring, or in a passphrase encrypted file when there is no keyring.
+    CANVAS_TOKEN_STORE=keyring|encrypted-file picks one explicitly.
+Return: the store the token ended up in
+ */
+pub fn save_token(token: &str) -> Result<StoreKind, CanvasError> {
+    match env::var("CANVAS_TOKEN_STORE").as_deref() {
+        Ok("keyring") => return save_in(StoreKind::Keyring, token),
+        Ok("encrypted-file") => return save_in(StoreKind::EncryptedFile, token),
+        Ok(other) => {
+            return Err(CanvasError::Config(format!(
+                "Unknown CANVAS_TOKEN_STORE {}, expected keyring or encrypted-file",
+                other
+            )))
+        }
+        Err(_) => (),
+    }
+    match save_in(StoreKind::Keyring, token) {
+        Ok(kind) => Ok(kind),
+        Err(err) => {
+            eprintln!(
+                "No OS keyring available ({}), encrypting the token with a passphrase instead",
+                err
+            );
+            save_in(StoreKind::EncryptedFile, token)
+        }
+    }
+}
+
+fn save_in(kind: StoreKind, token: &str) -> Result<StoreKind, CanvasError> {
+    let store = open_store(kind)?;
+    store.set(token)?;
+    Ok(store.kind())
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Credentials {
+    pub school_base_url: Option<String>,
+    // Where the token is kept, the token itself never goes in this file
+    pub token_store: Option<StoreKind>,
+    // The store holds an OAuth token (JSON with the refresh token) instead of an access token
+    #[serde(default)]
+    pub oauth: bool,
+    // Time zone dates are shown in, see `canvas profile timezone`; None for local time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_zone: Option<String>,
+    // CANVAS_AUTH_TOKEN, read by load and used instead of the saved token
+    #[serde(skip)]
+    pub(crate) env_token: Option<String>,
+    // Holds the token instead of the store token_store names, see with_store
+    #[serde(skip)]
+    pub(crate) store: Option<Box<dyn CredentialStore + Send + Sync>>,
+}
+
+impl Credentials {
+    // The profile's credentials.json as saved, without looking at the environment
+    pub fn load_saved() -> Result<Credentials, CanvasError> {
+        Credentials::load_profile(&state::profile())
+    }
+
+    // Another profile's credentials.json, used by `canvas profile`
+    pub fn load_profile(profile: &str) -> Result<Credentials, CanvasError> {
+        let path = state::profile_config_file(profile, "credentials.json")?;
+        match state::read_optional(&path)? {
+            Some(text) => error::decode(&path.to_string_lossy(), &text),
+            None => Ok(Credentials::default()),
+        }
+    }
+
+    /*
+    function: Credentials::load
+    Description: Credentials saved by `canvas login` for the current profile.
+        SCHOOL_BASE_URL in the environment takes precedence, which is handy for scripts.
+     */
+    pub fn load() -> Result<Credentials, CanvasError> {
+        let mut credentials = Credentials::load_saved()?;
+        if let Ok(school_base_url) = env::var("SCHOOL_BASE_URL") {
+            credentials.school_base_url = Some(school_base_url);
+        }
+        credentials.env_token = env::var("CANVAS_AUTH_TOKEN").ok();
+        Ok(credentials)
+    }
+
+    // Reads and saves the token in `store`, so tests never touch the profile's keyring or files
+    #[cfg(test)]
+    pub fn with_store(self, store: impl CredentialStore + Send + Sync + 'static) -> Credentials {
+        Credentials {
+            store: Some(Box::new(store)),
+            ..self
+        }
+    }
+
+    pub fn save(&self) -> Result<PathBuf, CanvasError> {
+        let path = credentials_path()?;
+        state::write_json(&path, self)?;
+        Ok(path)
+    }
+
+    pub fn school_base_url(&self) -> Result<&str, CanvasError> {
+        self.school_base_url.as_deref().ok_or_else(|| {
+            let hint = if state::has_legacy_state() {
+                ", or `canvas migrate` to use the credentials an older version left here"
+            } else {
+                ""
+            };
+            CanvasError::Config(format!(
+                "No Canvas school URL found, run `canvas login` first{}",
+                hint
+            ))
+        })
+    }
+
+    // CANVAS_AUTH_TOKEN in the environment wins, otherwise the token comes from its store
+    pub fn auth_token(&self) -> Result<String, CanvasError> {
+        if let Some(token) = &self.env_token {
+            return Ok(token.clone());
+        }
+        self.secret()
+    }
+
+    pub fn uses_oauth(&self) -> bool {
+        self.oauth && self.env_token.is_none()
+    }
+
+    // The OAuth token saved by `canvas login --oauth`, None when logged in with an access token
+    pub fn oauth_token(&self) -> Result<Option<OAuthToken>, CanvasError> {
+        if !self.uses_oauth() {
+            return Ok(None);
+        }
+        let source = format!("the {} OAuth token", state::profile());
+        error::decode(&source, &self.secret()?).map(Some)
+    }
+
+    // Replaces the saved OAuth token, e.g. after refreshing it
+    pub fn save_oauth_token(&self, token: &OAuthToken) -> Result<(), CanvasError> {
+        let kind = self.token_store.ok_or_else(not_logged_in)?;
+        let secret = serde_json::to_string(token).map_err(|err| CanvasError::Io(err.into()))?;
+        if let Some(store) = &self.store {
+            return store.set(&secret);
+        }
+        open_store(kind)?.set(&secret)?;
+        *SECRET.lock().unwrap() = Some(secret);
+        Ok(())
+    }
+
+    fn secret(&self) -> Result<String, CanvasError> {
+        if let Some(store) = &self.store {
+            return store.get()?.ok_or_else(not_logged_in);
+        }
+        let mut cached = SECRET.lock().unwrap();
+        if let Some(secret) = cached.as_ref() {
+            return Ok(secret.clone());
+        }
+        let kind = self.token_store.ok_or_else(not_logged_in)?;
+        let secret = open_store(kind)?.get()?.ok_or_else(not_logged_in)?;
+        *cached = Some(secret.clone());
+        Ok(secret)
+    }
+}
+
+fn not_logged_in() -> CanvasError {
+    CanvasError::Config("No Canvas auth token found, run `canvas login` first".to_string())
+}
+
+pub fn credentials_path() -> Result<PathBuf, CanvasError> {
+    state::config_file("credentials.json")
+}
+
+// OS keyring: Secret Service on Linux, Keychain on macOS, Credential Manager on Windows
+pub struct KeyringStore {
+    entry: keyring::Entry,
+}
+
+impl KeyringStore {
+    pub fn new() -> Result<KeyringStore, CanvasError> {
+        KeyringStore::for_profile(&state::profile())
+    }
+
+    // Each profile has its own keyring entry, named after the profile
+    pub fn for_profile(profile: &str) -> Result<KeyringStore, CanvasError> {
+        let entry = keyring::Entry::new(KEYRING_SERVICE, profile).map_err(keyring_error)?;
+        Ok(KeyringStore { entry })
+    }
+}
+
+fn keyring_error(err: keyring::Error) -> CanvasError {
+    CanvasError::Config(format!("Could not use the OS keyring: {}", err))
+}
+
+impl CredentialStore for KeyringStore {
+    fn kind(&self) -> StoreKind {
+        StoreKind::Keyring
+    }
+
+    fn get(&self) -> Result<Option<String>, CanvasError> {
+        match self.entry.get_password() {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(err) => Err(keyring_error(err)),
+        }
+    }
+
+    fn set(&self, secret: &str) -> Result<(), CanvasError> {
+        self.entry.set_password(secret).map_err(keyring_error)
+    }
+
+    fn delete(&self) -> Result<(), CanvasError> {
+        match self.entry.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(err) => Err(keyring_error(err)),
+        }
+    }
+}
+
+// token.enc in the profile's config directory, AES-256-GCM with a key derived from a passphrase by Argon2id.
+// The passphrase is read from CANVAS_PASSPHRASE or asked for on the terminal.
+pub struct EncryptedFileStore {
+    path: PathBuf,
+    // Given by in_dir, otherwise read as described above
+    passphrase: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedToken {
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl EncryptedFileStore {
+    pub fn new() -> Result<EncryptedFileStore, CanvasError> {
+        Ok(EncryptedFileStore {
+            path: state::config_file("token.enc")?,
+            passphrase: None,
+        })
+    }
+
+    // token.enc in `dir` instead of the profile's config directory, encrypted with `passphrase`
+    #[cfg(test)]
+    pub fn in_dir(dir: &std::path::Path, passphrase: &str) -> EncryptedFileStore {
+        EncryptedFileStore {
+            path: dir.join("token.enc"),
+            passphrase: Some(passphrase.to_string()),
+        }
+    }
+
+    fn passphrase(&self, confirm: bool) -> Result<String, CanvasError> {
+        if let Some(passphrase) = &self.passphrase {
+            return Ok(passphrase.clone());
+        }
+        if let Ok(passphrase) = env::var("CANVAS_PASSPHRASE") {
+            return Ok(passphrase);
+        }
+        if let Some(passphrase) = PASSPHRASE.get() {
+            return Ok(passphrase.clone());
+        }
+        eprint!("Passphrase for your Canvas token: ");
+        std::io::stderr().flush()?;
+        let passphrase = read_password()?;
+        if confirm {
+            eprint!("Confirm passphrase: ");
+            std::io::stderr().flush()?;
+            if read_password()? != passphrase {
+                return Err(CanvasError::Usage("Passphrases do not match".to_string()));
+            }
+        }
+        if passphrase.is_empty() {
+            return Err(CanvasError::Usage(
+                "Passphrase can not be empty".to_string(),
+            ));
+        }
+        Ok(PASSPHRASE.get_or_init(|| passphrase).clone())
+    }
+
+    fn cipher(passphrase: &str, salt: &[u8]) -> Result<Aes256Gcm, CanvasError> {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|err| CanvasError::Config(format!("Could not derive a key: {}", err)))?;
+        Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)))
+    }
+}
+
+impl CredentialStore for EncryptedFileStore {
+    fn kind(&self) -> StoreKind {
+        StoreKind::EncryptedFile
+    }
+
+    fn get(&self) -> Result<Option<String>, CanvasError> {
+        let source = self.path.to_string_lossy();
+        let text = match state::read_optional(&self.path)? {
+            Some(text) => text,
+            None => return Ok(None),
+        };
+        let encrypted: EncryptedToken = error::decode(&source, &text)?;
+        let decode_base64 = |field: &str, value: &str| {
+            BASE64.decode(value).map_err(|err| CanvasError::Decode {
+                source: source.to_string(),
+                path: field.to_string(),
+                message: err.to_string(),
+            })
+        };
+        let salt = decode_base64("salt", &encrypted.salt)?;
+        let nonce = decode_base64("nonce", &encrypted.nonce)?;
+        let ciphertext = decode_base64("ciphertext", &encrypted.ciphertext)?;
+        if nonce.len() != 12 {
+            return Err(CanvasError::Decode {
+                source: source.to_string(),
+                path: "nonce".to_string(),
+                message: "expected 12 bytes".to_string(),
+            });
+        }
+
+        let cipher = EncryptedFileStore::cipher(&self.passphrase(false)?, &salt)?;
+        let token = cipher
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| {
+                CanvasError::Config("Wrong passphrase for the Canvas token".to_string())
+            })?;
+        String::from_utf8(token)
+            .map(Some)
+            .map_err(|err| CanvasError::Decode {
+                source: source.to_string(),
+                path: "ciphertext".to_string(),
+                message: err.to_string(),
+            })
+    }
+
+    fn set(&self, secret: &str) -> Result<(), CanvasError> {
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let cipher = EncryptedFileStore::cipher(&self.passphrase(true)?, &salt)?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, secret.as_bytes())
+            .map_err(|_| CanvasError::Config("Could not encrypt the Canvas token".to_string()))?;
+        let encrypted = EncryptedToken {
+            salt: BASE64.encode(salt),
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        };
+        state::write_json(&self.path, &encrypted)
+    }
+
+    fn delete(&self) -> Result<(), CanvasError> {
+        match std::fs::remove_file(&self.path) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(error::file_error("delete", &self.path)(err)),
+        }
+    }
+}
@@ -1,36 +1,157 @@
+use crate::dates;
+use crate::output::{Column, Record};
+use chrono::{DateTime, FixedOffset};
 use serde::{Deserialize, Serialize};
 
 // Use this site to construct the structs: https://quicktype.io/
 
-#[derive(Debug)]
-#[allow(dead_code)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ValidCourse {
     pub name: String,
     pub course_code: String,
     pub id: i64,
+    // IANA name like America/Chicago, set by the teacher in Canvas
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_zone: Option<String>,
 }
 
 impl ValidCourse {
-    pub fn new(name: String, course_code: String, id: i64) -> ValidCourse {
+    pub fn new(
+        name: String,
+        course_code: String,
+        id: i64,
+        time_zone: Option<String>,
+    ) -> ValidCourse {
         ValidCourse {
             name,
             course_code,
             id,
+            time_zone,
         }
     }
 }
 
-#[derive(Debug)]
-#[allow(dead_code)]
+#[derive(Debug, Serialize)]
 pub struct ValidAssignment {
     pub name: String,
     pub id: i64,
-    pub due_at: String,
+    // RFC 3339, None for assignments without a due date
+    pub due_at: Option<String>,
+    // due_at as a time in the zone it is shown in, for filtering, sorting and the table
+    #[serde(skip)]
+    pub due_date: Option<DateTime<FixedOffset>>,
+    pub course_id: i64,
+    pub points_possible: Option<f32>,
+    pub submission_types: Vec<String>,
+    // File extensions an upload has to have, empty when any file is accepted
+    pub allowed_extensions: Vec<String>,
+    pub lock_at: Option<String>,
+    pub locked_for_user: bool,
+    // None or -1 when there is no limit
+    pub allowed_attempts: Option<i64>,
+    pub submitted: bool,
+    // Canvas' workflow state of our submission: unsubmitted, submitted, graded or pending_review
+    pub submission_state: Option<String>,
+}
+
+impl Record for ValidCourse {
+    fn columns() -> Vec<Column> {
+        vec![
+            Column::new("course_code", "Course Code").alias(&["code"]),
+            Column::new("name", "Course Name"),
+            Column::id("id", "Course ID"),
+        ]
+    }
+
+    fn values(&self) -> Vec<String> {
+        vec![
+            self.course_code.clone(),
+            self.name.clone(),
+            self.id.to_string(),
+        ]
+    }
 }
 
 impl ValidAssignment {
-    pub fn new(name: String, id: i64, due_at: String) -> ValidAssignment {
-        ValidAssignment { name, id, due_at }
+    // None for assignments Canvas sent without an id or name
+    pub fn new(
+        assignment: &Assignment,
+        due_date: Option<DateTime<FixedOffset>>,
+        course_id: i64,
+    ) -> Option<ValidAssignment> {
+        let submission = assignment.submission.as_ref();
+        let submission_state = submission.and_then(|submission| submission.workflow_state.clone());
+        Some(ValidAssignment {
+            name: assignment.name.clone()?,
+            id: assignment.id?,
+            due_at: due_date.map(|due_date| due_date.to_rfc3339()),
+            due_date,
+            course_id,
+            points_possible: assignment.points_possible,
+            submission_types: assignment.submission_types.clone().unwrap_or_default(),
+            allowed_extensions: assignment.allowed_extensions.clone().unwrap_or_default(),
+            lock_at: assignment.lock_at.clone(),
+            locked_for_user: assignment.locked_for_user.unwrap_or(false),
+            allowed_attempts: assignment.allowed_attempts,
+            submitted: submission.is_some_and(AssignmentSubmission::is_submitted),
+            submission_state,
+        })
+    }
+
+    // The due date in the --date-format, for people to read
+    pub fn due(&self) -> Option<String> {
+        self.due_date.map(dates::format)
+    }
+
+    // The table shows the due date in the --date-format and yes/no for locked, CSV and TSV keep the
+    // serialized values
+    fn row(&self, table: bool) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            if table {
+                self.due()
+            } else {
+                self.due_at.clone()
+            }
+            .unwrap_or_default(),
+            self.points_possible
+                .map(|points| points.to_string())
+                .unwrap_or_default(),
+            self.submission_types.join(", "),
+            match (table, self.locked_for_user) {
+                (true, true) => "yes".to_string(),
+                (true, false) => "no".to_string(),
+                (false, locked) => locked.to_string(),
+            },
+            self.submission_state
+                .clone()
+                .unwrap_or_else(|| "unsubmitted".to_string()),
+            self.id.to_string(),
+            self.course_id.to_string(),
+        ]
+    }
+}
+
+impl Record for ValidAssignment {
+    fn columns() -> Vec<Column> {
+        vec![
+            Column::new("name", "Assignment Name"),
+            Column::new("due_at", "Due Date").alias(&["due"]),
+            Column::new("points_possible", "Points").alias(&["points"]),
+            Column::new("submission_types", "Type").alias(&["type", "types"]),
+            Column::new("locked_for_user", "Locked").alias(&["locked"]),
+            Column::new("submission_state", "Submitted").alias(&["submitted", "state"]),
+            Column::id("id", "Assignment ID"),
+            Column::new("course_id", "Course ID"),
+        ]
+    }
+
+    fn values(&self) -> Vec<String> {
+        self.row(false)
+    }
+
+    fn table_values(&self) -> Vec<String> {
+        self.row(true)
     }
 }
 
@@ -47,6 +168,24 @@ pub struct Account {
     permissions: Permissions,
 }
 
+impl Record for Account {
+    fn columns() -> Vec<Column> {
+        vec![
+            Column::new("name", "Name"),
+            Column::id("id", "ID"),
+            Column::new("created_at", "Date Created"),
+        ]
+    }
+
+    fn values(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.id.to_string(),
+            self.created_at.clone(),
+        ]
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Permissions {
     can_update_name: bool,
@@ -80,7 +219,7 @@ pub struct Course {
     friendly_name: Option<serde_json::Value>,
     apply_assignment_group_weights: Option<bool>,
     calendar: Option<Calendar>,
-    time_zone: Option<String>,
+    pub time_zone: Option<String>,
     blueprint: Option<bool>,
     template: Option<bool>,
     enrollments: Option<Vec<Enrollment>>,
@@ -116,14 +255,14 @@ pub struct Assignment {
     created_at: Option<String>,
     updated_at: Option<String>,
     pub due_at: Option<String>,
-    lock_at: Option<String>,
-    unlock_at: Option<String>,
+    pub lock_at: Option<String>,
+    pub unlock_at: Option<String>,
     has_overrides: Option<bool>,
     all_dates: Option<serde_json::Value>,
     course_id: Option<i64>,
     assignment_group_id: Option<i64>,
     due_date_required: Option<bool>,
-    allowed_extensions: Option<Vec<String>>,
+    pub allowed_extensions: Option<Vec<String>>,
     max_name_length: Option<i64>,
     turnitin_enabled: Option<bool>,
     vericite_enabled: Option<bool>,
@@ -142,24 +281,25 @@ pub struct Assignment {
     post_to_sis: Option<bool>,
     integration_id: Option<String>,
     integration_data: Option<IntegrationData>,
-    points_possible: Option<f32>,
-    submission_types: Option<Vec<String>>,
+    pub points_possible: Option<f32>,
+    pub submission_types: Option<Vec<String>>,
     has_submitted_submissions: Option<bool>,
     grading_type: Option<String>,
     grading_standard_id: Option<serde_json::Value>,
     published: Option<bool>,
     unpublishable: Option<bool>,
     only_visible_to_overrides: Option<bool>,
-    locked_for_user: Option<bool>,
+    pub locked_for_user: Option<bool>,
     lock_info: Option<LockInfo>,
-    lock_explanation: Option<String>,
-    quiz_id: Option<i64>,
+    pub lock_explanation: Option<String>,
+    pub quiz_id: Option<i64>,
     anonymous_submissions: Option<bool>,
-    discussion_topic: Option<serde_json::Value>,
+    pub discussion_topic: Option<serde_json::Value>,
     freeze_on_copy: Option<bool>,
     frozen: Option<bool>,
     frozen_attributes: Option<Vec<String>>,
-    submission: Option<serde_json::Value>,
+    // Only sent when asked for with include[]=submission
+    pub submission: Option<AssignmentSubmission>,
     use_rubric_for_grading: Option<bool>,
     rubric_settings: Option<RubricSettings>,
     rubric: Option<Rubric>,
@@ -173,10 +313,10 @@ pub struct Assignment {
     graders_anonymous_to_graders: Option<bool>,
     grader_names_visible_to_final_grader: Option<bool>,
     anonymous_grading: Option<bool>,
-    allowed_attempts: Option<i64>,
+    pub allowed_attempts: Option<i64>,
     post_manually: Option<bool>,
     score_statistics: Option<serde_json::Value>,
-    can_submit: Option<bool>,
+    pub can_submit: Option<bool>,
     annotatable_attachment_id: Option<serde_json::Value>,
     anonymize_students: Option<bool>,
     require_lockdown_browser: Option<bool>,
@@ -184,6 +324,88 @@ pub struct Assignment {
     muted: Option<bool>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AssignmentSubmission {
+    pub workflow_state: Option<String>,
+    pub submitted_at: Option<String>,
+    pub attempt: Option<i64>,
+}
+
+impl AssignmentSubmission {
+    pub fn is_submitted(&self) -> bool {
+        self.submitted_at.is_some()
+            || matches!(
+                self.workflow_state.as_deref(),
+                Some("submitted" | "graded" | "pending_review")
+            )
+    }
+}
+
+// An item of the planner, /api/v1/planner/items
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlannerItem {
+    pub course_id: Option<i64>,
+    pub context_name: Option<String>,
+    pub plannable_id: Option<i64>,
+    // assignment, quiz, discussion_topic, wiki_page, planner_note, calendar_event, ...
+    pub plannable_type: Option<String>,
+    pub plannable_date: Option<String>,
+    pub plannable: Option<Plannable>,
+    pub submissions: Option<PlannerSubmissions>,
+    pub planner_override: Option<PlannerOverride>,
+    pub html_url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Plannable {
+    pub title: Option<String>,
+    pub due_at: Option<String>,
+}
+
+// Canvas sends false instead of an object for items that take no submissions
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PlannerSubmissions {
+    Status(PlannerSubmissionStatus),
+    NotSubmittable(bool),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlannerSubmissionStatus {
+    #[serde(default)]
+    pub submitted: bool,
+    #[serde(default)]
+    pub excused: bool,
+    #[serde(default)]
+    pub missing: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlannerOverride {
+    #[serde(default)]
+    pub marked_complete: bool,
+}
+
+// An item of the to do list, /api/v1/users/self/todo
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TodoEntry {
+    // submitting, or grading for teachers
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub assignment: Option<Assignment>,
+    pub quiz: Option<TodoQuiz>,
+    pub course_id: Option<i64>,
+    pub context_name: Option<String>,
+    pub html_url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TodoQuiz {
+    pub id: Option<i64>,
+    pub title: Option<String>,
+    pub due_at: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LockInfo {
     asset_string: Option<String>,
@@ -253,6 +475,30 @@ pub struct FileUpload {
 pub struct UploadParams {
     filename: Option<String>,
     pub content_type: Option<String>,
+    // Some storage backends ask for more params (policy, signature, ...)
+    #[serde(flatten)]
+    other: serde_json::Map<String, serde_json::Value>,
+}
+
+impl UploadParams {
+    // Every param as form fields, filename and content_type first
+    pub fn fields(&self) -> Vec<(String, String)> {
+        let mut fields = Vec::new();
+        if let Some(filename) = &self.filename {
+            fields.push(("filename".to_string(), filename.clone()));
+        }
+        if let Some(content_type) = &self.content_type {
+            fields.push(("content_type".to_string(), content_type.clone()));
+        }
+        for (name, value) in &self.other {
+            let value = match value {
+                serde_json::Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            fields.push((name.clone(), value));
+        }
+        fields
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -0,0 +1,170 @@
+use crate::cli::DateFormat;
+use crate::credentials::Credentials;
+use crate::error::CanvasError;
+use chrono::{DateTime, FixedOffset, Local, Locale, NaiveDate, Utc};
+use chrono_tz::Tz;
+use colored::Colorize;
+use std::env;
+use std::sync::OnceLock;
+
+// Canvas sends every time in UTC. Times are shown in the zone picked with --timezone, the profile's
+// zone (`canvas profile timezone`) or the local one, in the format picked with --date-format.
+
+// Set once by run(), before anything is fetched
+static SETTINGS: OnceLock<DateSettings> = OnceLock::new();
+
+#[derive(Debug, Clone)]
+pub enum Zone {
+    Local,
+    Named(Tz),
+    // The time zone of the course a date belongs to, set by its teacher in Canvas
+    Course,
+}
+
+#[derive(Debug)]
+struct DateSettings {
+    zone: Zone,
+    format: DateFormat,
+}
+
+/*
+function: configure
+Description: Picks the zone and format of every date printed afterwards: --timezone, then the
+    profile's zone, then the local one
+ */
+pub fn configure(zone: Option<Zone>, format: DateFormat) -> Result<(), CanvasError> {
+    let zone = match zone {
+        Some(zone) => zone,
+        None => match Credentials::load_saved()?.time_zone {
+            Some(name) => parse_zone(&name).map_err(CanvasError::Config)?,
+            None => Zone::Local,
+        },
+    };
+    SETTINGS.set(DateSettings { zone, format }).ok();
+    Ok(())
+}
+
+fn settings() -> &'static DateSettings {
+    SETTINGS.get_or_init(|| DateSettings {
+        zone: Zone::Local,
+        format: DateFormat::Locale,
+    })
+}
+
+// Accepts IANA names like America/Chicago, "local" and "course"
+pub fn parse_zone(name: &str) -> Result<Zone, String> {
+    match name.trim() {
+        "local" => Ok(Zone::Local),
+        "course" => Ok(Zone::Course),
+        name => name.parse().map(Zone::Named).map_err(|_| {
+            format!(
+                "Unknown time zone {}, use a name like America/Chicago, local or course",
+                name
+            )
+        }),
+    }
+}
+
+// Whether dates need the time zone of their course, which has to be fetched for them
+pub fn uses_course_zone() -> bool {
+    matches!(settings().zone, Zone::Course)
+}
+
+// A time Canvas sent in `field`, an error naming the field and value instead of a panic when it is
+// not RFC 3339
+pub fn parse(field: &str, value: &str) -> Result<DateTime<FixedOffset>, CanvasError> {
+    DateTime::parse_from_rfc3339(value).map_err(|err| CanvasError::Decode {
+        source: "Canvas".to_string(),
+        path: field.to_string(),
+        message: format!("{:?} is not a valid date: {}", value, err),
+    })
+}
+
+// Parses the date of a listed item, one that can not be read is reported and the item listed without it
+pub fn parse_reported(
+    field: &str,
+    value: &str,
+    item: Option<&str>,
+) -> Option<DateTime<FixedOffset>> {
+    parse(field, value)
+        .map_err(|err| {
+            eprintln!(
+                "{} {}: {}",
+                "Could not read the date of".yellow(),
+                item.unwrap_or("an item"),
+                err
+            )
+        })
+        .ok()
+}
+
+/*
+function: localize
+Description: Moves a time into the zone it is shown in. `course_zone` is the course's time zone
+    from Canvas, used with --timezone course; an unknown or missing one falls back to local time.
+ */
+pub fn localize(time: DateTime<FixedOffset>, course_zone: Option<&str>) -> DateTime<FixedOffset> {
+    let named = match &settings().zone {
+        Zone::Local => None,
+        Zone::Named(tz) => Some(*tz),
+        Zone::Course => course_zone.and_then(|zone| zone.parse::<Tz>().ok()),
+    };
+    match named {
+        Some(tz) => time.with_timezone(&tz).fixed_offset(),
+        None => time.with_timezone(&Local).fixed_offset(),
+    }
+}
+
+// Today's date in the zone a time was localized to
+pub fn today_in(zone_of: &DateTime<FixedOffset>) -> NaiveDate {
+    Utc::now().with_timezone(&zone_of.timezone()).date_naive()
+}
+
+// A localized time in the format picked with --date-format
+pub fn format(time: DateTime<FixedOffset>) -> String {
+    match settings().format {
+        DateFormat::Iso => time.to_rfc3339(),
+        DateFormat::Locale => time.format_localized("%a %x %H:%M", locale()).to_string(),
+        DateFormat::Relative => relative(time),
+    }
+}
+
+// The locale of LC_ALL, LC_TIME or LANG, e.g. de_DE.UTF-8 becomes de_DE; en_US when none is usable
+fn locale() -> Locale {
+    ["LC_ALL", "LC_TIME", "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok().filter(|value| !value.is_empty()))
+        .map(|value| {
+            let name = value.split(['.', '@']).next().unwrap_or_default();
+            Locale::try_from(name).ok()
+        })
+        .next()
+        .flatten()
+        .filter(|locale| *locale != Locale::POSIX)
+        .unwrap_or(Locale::en_US)
+}
+
+// How far a localized time is from now, e.g. "in 45m", "in 3h", "tomorrow 09:00", "in 4 days", "2h ago"
+pub fn relative(time: DateTime<FixedOffset>) -> String {
+    let now = Utc::now().with_timezone(&time.timezone());
+    let delta = time - now;
+    let days = (time.date_naive() - now.date_naive()).num_days();
+    let minutes = delta.num_minutes();
+    match minutes {
+        -59..=59 if minutes >= 0 => format!("in {}m", minutes),
+        -59..=59 => format!("{}m ago", -minutes),
+        _ if delta.num_hours().abs() < 12 || days == 0 => {
+            if minutes > 0 {
+                format!("in {}h", delta.num_hours())
+            } else {
+                format!("{}h ago", -delta.num_hours())
+            }
+        }
+        _ => match days {
+            1 => format!("tomorrow {}", time.format("%H:%M")),
+            -1 => format!("yesterday {}", time.format("%H:%M")),
+            days if days > 0 => format!("in {} days", days),
+            days => format!("{} days ago", -days),
+        },
+    }
+}
@@ -0,0 +1,56 @@
+use crate::error::{self, CanvasError};
+use crate::staging::{self, StagingIndex};
+use crate::state;
+use std::env;
+use std::fs;
+use std::process::Command;
+
+// `canvas commit` without -m asks for the submission comment in an editor, like `git commit` does:
+// $VISUAL, then $EDITOR, then vi, on COMMENT_EDITMSG in the profile's data directory
+
+const COMMENT_FILE: &str = "COMMENT_EDITMSG";
+
+/*
+function: edit_comment
+Description: Opens the editor on the current comment and the files about to be committed
+Return: the comment without the # lines, empty when the user deleted everything
+ */
+pub fn edit_comment(index: &StagingIndex) -> Result<String, CanvasError> {
+    let mut template = index.message.clone().unwrap_or_default();
+    template.push_str(
+        "\n\n# Write a comment for the grader, it is sent with the next `canvas submit`.\n\
+         # Lines starting with '#' are ignored, an empty comment sends none.\n#\n# Committing:\n",
+    );
+    for staged in index.staged() {
+        template.push_str(&format!("#   {}\n", staging::display_path(staged)));
+    }
+    let path = state::data_file(COMMENT_FILE)?;
+    state::write_private(&path, template.as_bytes())?;
+
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+    // The editor may come with arguments, e.g. "code --wait"
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = Command::new(program)
+        .args(words)
+        .arg(&path)
+        .status()
+        .map_err(|err| {
+            CanvasError::Config(format!("Could not start the editor {}: {}", editor, err))
+        })?;
+    if !status.success() {
+        return Err(CanvasError::Config(format!(
+            "The editor {} exited with {}, nothing was committed",
+            editor, status
+        )));
+    }
+
+    let text = fs::read_to_string(&path).map_err(error::file_error("read", &path))?;
+    fs::remove_file(&path).ok();
+    let comment: Vec<&str> = text.lines().filter(|line| !line.starts_with('#')).collect();
+    Ok(comment.join("\n").trim().to_string())
+}
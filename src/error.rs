@@ -0,0 +1,200 @@
+use reqwest::StatusCode;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+// Every failure the CLI can run into, so run() can explain what went wrong
+// and main() can exit with a code scripts are able to branch on
+#[derive(Debug)]
+pub enum CanvasError {
+    // Bad command line input
+    Usage(String),
+    // Missing local configuration, e.g. the user has not logged in yet
+    Config(String),
+    // Canvas rejected the auth token (401)
+    Unauthorized,
+    // Canvas could not find what was asked for (404), holds a description of it
+    NotFound(String),
+    // Any other unsuccessful response along with the messages from Canvas' `errors` body
+    Status {
+        status: StatusCode,
+        url: String,
+        messages: Vec<String>,
+    },
+    // The request never got a response
+    Network(reqwest::Error),
+    // A response or local file did not have the expected shape, path points at the bad field
+    Decode {
+        source: String,
+        path: String,
+        message: String,
+    },
+    Io(io::Error),
+    // A local file or directory could not be read, written or removed
+    File {
+        action: &'static str,
+        path: String,
+        err: io::Error,
+    },
+    // The assignment would not take the submission (file types, attempts, locks), one line per problem
+    Rejected(Vec<String>),
+}
+
+impl CanvasError {
+    /*
+    function: exit_code
+    Description: Distinct process exit code for each kind of failure
+        2 usage, 3 config, 4 unauthorized, 5 not found, 6 other http status,
+        7 network, 8 decode, 9 io, 10 rejected by the pre-flight check
+     */
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CanvasError::Usage(_) => 2,
+            CanvasError::Config(_) => 3,
+            CanvasError::Unauthorized => 4,
+            CanvasError::NotFound(_) => 5,
+            CanvasError::Status { .. } => 6,
+            CanvasError::Network(_) => 7,
+            CanvasError::Decode { .. } => 8,
+            CanvasError::Io(_) | CanvasError::File { .. } => 9,
+            CanvasError::Rejected(_) => 10,
+        }
+    }
+
+    // Replaces the bare url of a 404 with a description of what was being looked up
+    pub fn or_not_found(self, what: impl Into<String>) -> CanvasError {
+        match self {
+            CanvasError::NotFound(_) => CanvasError::NotFound(what.into()),
+            other => other,
+        }
+    }
+
+    // Builds the error for an unsuccessful response from its status and body
+    pub fn from_response(status: StatusCode, url: &str, body: &str) -> CanvasError {
+        match status {
+            StatusCode::UNAUTHORIZED => CanvasError::Unauthorized,
+            StatusCode::NOT_FOUND => CanvasError::NotFound(format!("{} not found", url)),
+            _ => CanvasError::Status {
+                status,
+                url: url.to_string(),
+                messages: canvas_messages(body),
+            },
+        }
+    }
+}
+
+// Canvas reports errors as {"errors": [{"message": ...}]}, {"errors": {"field": [{"message": ...}]}}
+// or just {"message": ...}, so collect every message string we can find
+fn canvas_messages(body: &str) -> Vec<String> {
+    fn collect(value: &serde_json::Value, messages: &mut Vec<String>) {
+        match value {
+            serde_json::Value::String(message) => messages.push(message.clone()),
+            serde_json::Value::Array(values) => values.iter().for_each(|v| collect(v, messages)),
+            serde_json::Value::Object(map) => match map.get("message") {
+                Some(serde_json::Value::String(message)) => messages.push(message.clone()),
+                _ => map.values().for_each(|v| collect(v, messages)),
+            },
+            _ => (),
+        }
+    }
+
+    let mut messages = Vec::new();
+    if let Ok(json) = serde_json::from_str::<serde_json::Value>(body) {
+        match (json.get("errors"), json.get("message")) {
+            (Some(errors), _) => collect(errors, &mut messages),
+            (None, Some(message)) => collect(message, &mut messages),
+            _ => (),
+        }
+    }
+    messages
+}
+
+impl fmt::Display for CanvasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CanvasError::Usage(message) => write!(f, "{}", message),
+            CanvasError::Config(message) => write!(f, "{}", message),
+            CanvasError::Unauthorized => write!(
+                f,
+                "Canvas rejected your token, it may have expired. Run `canvas login` again"
+            ),
+            CanvasError::NotFound(what) => write!(f, "{}", what),
+            CanvasError::Status {
+                status,
+                url,
+                messages,
+            } => {
+                write!(f, "Canvas returned {} for {}", status, url)?;
+                if !messages.is_empty() {
+                    write!(f, ": {}", messages.join("; "))?;
+                }
+                Ok(())
+            }
+            CanvasError::Network(err) => write!(f, "Could not reach Canvas: {}", err),
+            CanvasError::Decode {
+                source,
+                path,
+                message,
+            } => write!(
+                f,
+                "Unexpected data from {} at `{}`: {}",
+                source, path, message
+            ),
+            CanvasError::Io(err) => write!(f, "{}", err),
+            CanvasError::File { action, path, err } => {
+                // "No such file or directory (os error 2)" reads better without the code
+                let message = err.to_string();
+                let message = message.split(" (os error").next().unwrap_or(&message);
+                write!(f, "Cannot {} {}: {}", action, path, message)
+            }
+            CanvasError::Rejected(problems) => {
+                writeln!(f, "The assignment would not accept this submission:")?;
+                for problem in problems {
+                    writeln!(f, "  - {}", problem)?;
+                }
+                write!(f, "Nothing was submitted, use --force to submit anyway")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CanvasError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CanvasError::Network(err) => Some(err),
+            CanvasError::Io(err) | CanvasError::File { err, .. } => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for CanvasError {
+    fn from(err: reqwest::Error) -> CanvasError {
+        CanvasError::Network(err)
+    }
+}
+
+impl From<io::Error> for CanvasError {
+    fn from(err: io::Error) -> CanvasError {
+        CanvasError::Io(err)
+    }
+}
+
+// For map_err on file operations, e.g. "Cannot read notes.pdf: No such file or directory"
+pub fn file_error(action: &'static str, path: &Path) -> impl FnOnce(io::Error) -> CanvasError {
+    let path = path.display().to_string();
+    move |err| CanvasError::File { action, path, err }
+}
+
+// Deserializes `text` keeping track of the JSON path, so decode errors say which field was wrong
+pub fn decode<T>(source: &str, text: &str) -> Result<T, CanvasError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let deserializer = &mut serde_json::Deserializer::from_str(text);
+    serde_path_to_error::deserialize(deserializer).map_err(|err| CanvasError::Decode {
+        source: source.to_string(),
+        path: err.path().to_string(),
+        message: err.inner().to_string(),
+    })
+}
@@ -0,0 +1,296 @@
+use crate::cli::{AssignmentFilter, AssignmentSort};
+use crate::data::ValidAssignment;
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveTime, TimeZone, Utc};
+use regex::{Regex, RegexBuilder};
+
+// Filters and sorting for `canvas assignments`. One of --overdue, --upcoming and --unsubmitted is
+// sent to Canvas as its `bucket` parameter so less comes over the wire, the rest is filtered here.
+// Everything is checked here again anyway, which keeps cached and offline listings correct too.
+
+// Submission types that do not expect anything to be handed in through Canvas
+pub const NOTHING_TO_SUBMIT: [&str; 3] = ["none", "on_paper", "not_graded"];
+
+impl AssignmentFilter {
+    // The bucket Canvas filters by: the narrowest of the filters given, if any
+    pub fn bucket(&self) -> Option<&'static str> {
+        if self.overdue {
+            Some("overdue")
+        } else if self.unsubmitted {
+            Some("unsubmitted")
+        } else if self.upcoming {
+            // "future" is every assignment due after now, undated ones are dropped here
+            Some("future")
+        } else {
+            None
+        }
+    }
+
+    // Whether any filter was given, --sort alone keeps every assignment
+    pub fn narrows(&self) -> bool {
+        self.bucket().is_some()
+            || self.due_before.is_some()
+            || self.due_after.is_some()
+            || self.kind.is_some()
+            || self.search.is_some()
+    }
+
+    pub fn apply(&self, mut assignments: Vec<ValidAssignment>) -> Vec<ValidAssignment> {
+        let now = Utc::now();
+        assignments.retain(|assignment| self.matches(assignment, now));
+        match self.sort {
+            // Canvas' order is kept for assignments due at the same time
+            AssignmentSort::Due => assignments
+                .sort_by_key(|assignment| (assignment.due_date.is_none(), assignment.due_date)),
+            AssignmentSort::Name => {
+                assignments.sort_by_key(|assignment| assignment.name.to_lowercase())
+            }
+            // Most points first, assignments without points last
+            AssignmentSort::Points => assignments.sort_by(|a, b| {
+                let a = a.points_possible.unwrap_or(f32::NEG_INFINITY);
+                let b = b.points_possible.unwrap_or(f32::NEG_INFINITY);
+                b.total_cmp(&a)
+            }),
+        }
+        assignments
+    }
+
+    fn matches(&self, assignment: &ValidAssignment, now: DateTime<Utc>) -> bool {
+        let due = assignment.due_date.map(|due| due.with_timezone(&Utc));
+        let expects_submission = !assignment.submission_types.is_empty()
+            && !assignment
+                .submission_types
+                .iter()
+                .all(|kind| NOTHING_TO_SUBMIT.contains(&kind.as_str()));
+        let unsubmitted = expects_submission && !assignment.submitted;
+
+        if self.upcoming && due.is_none_or(|due| due <= now) {
+            return false;
+        }
+        if self.overdue && !(unsubmitted && due.is_some_and(|due| due < now)) {
+            return false;
+        }
+        if self.unsubmitted && !unsubmitted {
+            return false;
+        }
+        if let Some(before) = self.due_before {
+            if due.is_none_or(|due| due >= before) {
+                return false;
+            }
+        }
+        if let Some(after) = self.due_after {
+            if due.is_none_or(|due| due <= after) {
+                return false;
+            }
+        }
+        if let Some(kind) = &self.kind {
+            if !assignment.submission_types.iter().any(|t| t == kind) {
+                return false;
+            }
+        }
+        if let Some(search) = &self.search {
+            if !search.is_match(&assignment.name) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/*
+function: parse_date
+Description: Parses the dates --due-before and --due-after take:
+    2026-05-01 (the start of that day in local time), an RFC 3339 time, today, tomorrow,
+    or a time from now like +3d, +2w or +12h
+ */
+pub fn parse_date(input: &str) -> Result<DateTime<Utc>, String> {
+    let input = input.trim();
+    let start_of = |date: NaiveDate| {
+        Local
+            .from_local_datetime(&date.and_time(NaiveTime::MIN))
+            .earliest()
+            .map(|start| start.with_timezone(&Utc))
+            .ok_or_else(|| format!("{} does not exist in the local time zone", date))
+    };
+    match input {
+        "today" => return start_of(Local::now().date_naive()),
+        "tomorrow" => return start_of(Local::now().date_naive() + Duration::days(1)),
+        _ => (),
+    }
+    if let Some(relative) = input.strip_prefix('+') {
+        // The unit is the last character, which need not be a single byte
+        let split = relative.char_indices().last().map_or(0, |(index, _)| index);
+        let (count, unit) = relative.split_at(split);
+        let count: i64 = count
+            .parse()
+            .map_err(|_| format!("expected a number before the unit in {}", input))?;
+        let duration = match unit {
+            "h" => Duration::try_hours(count),
+            "d" => Duration::try_days(count),
+            "w" => Duration::try_weeks(count),
+            _ => return Err(format!("unknown unit in {}, use h, d or w", input)),
+        };
+        return duration
+            .and_then(|duration| Utc::now().checked_add_signed(duration))
+            .ok_or_else(|| format!("{} is too far from now", input));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        return start_of(date);
+    }
+    DateTime::parse_from_rfc3339(input)
+        .map(|date| date.with_timezone(&Utc))
+        .map_err(|_| {
+            format!(
+                "{} is not a date, use e.g. 2026-05-01, 2026-05-01T17:00:00Z, today or +7d",
+                input
+            )
+        })
+}
+
+// --search is matched case insensitively
+pub fn parse_regex(input: &str) -> Result<Regex, String> {
+    RegexBuilder::new(input)
+        .case_insensitive(true)
+        .build()
+        .map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assignment(
+        name: &str,
+        due: Option<&str>,
+        types: &[&str],
+        submitted: bool,
+    ) -> ValidAssignment {
+        let due_date = due.map(|due| DateTime::parse_from_rfc3339(due).unwrap());
+        ValidAssignment {
+            name: name.to_string(),
+            id: 1,
+            due_at: due.map(str::to_string),
+            due_date,
+            course_id: 1,
+            points_possible: None,
+            submission_types: types.iter().map(|kind| kind.to_string()).collect(),
+            allowed_extensions: Vec::new(),
+            lock_at: None,
+            locked_for_user: false,
+            allowed_attempts: None,
+            submitted,
+            submission_state: None,
+        }
+    }
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2026-05-10T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn parses_relative_dates() {
+        let before = Utc::now();
+        let date = parse_date("+3d").unwrap();
+        assert!(date >= before + Duration::days(3));
+        assert!(date <= Utc::now() + Duration::days(3));
+        assert!(parse_date("+12h").unwrap() < parse_date("+1d").unwrap());
+        assert!(parse_date("+1w").unwrap() > parse_date("+6d").unwrap());
+    }
+
+    #[test]
+    fn parses_absolute_dates() {
+        assert_eq!(
+            parse_date("2026-05-01T17:00:00+02:00").unwrap(),
+            DateTime::parse_from_rfc3339("2026-05-01T15:00:00Z").unwrap()
+        );
+        let day = parse_date("2026-05-01").unwrap().with_timezone(&Local);
+        assert_eq!(
+            day.date_naive(),
+            NaiveDate::from_ymd_opt(2026, 5, 1).unwrap()
+        );
+        assert_eq!(day.time(), NaiveTime::MIN);
+        assert!(parse_date("today").unwrap() < parse_date("tomorrow").unwrap());
+    }
+
+    #[test]
+    fn rejects_bad_dates_without_panicking() {
+        for input in [
+            "",
+            "+",
+            "+d",
+            "+3",
+            "+3é",
+            "+é",
+            "+3y",
+            "+x3d",
+            "next friday",
+            "2026-13-01",
+        ] {
+            assert!(parse_date(input).is_err(), "{} was accepted", input);
+        }
+        assert!(parse_date("+999999999999d").is_err());
+        assert!(parse_date("+9223372036854775807h").is_err());
+        assert!(parse_date("+99999999999999w").is_err());
+    }
+
+    #[test]
+    fn matches_due_date_filters() {
+        let filter = AssignmentFilter {
+            due_after: Some(now()),
+            due_before: Some(now() + Duration::days(7)),
+            ..Default::default()
+        };
+        let matches = |due| filter.matches(&assignment("a", due, &["online_upload"], false), now());
+        assert!(matches(Some("2026-05-12T00:00:00Z")));
+        assert!(!matches(Some("2026-05-09T00:00:00Z")));
+        assert!(!matches(Some("2026-05-20T00:00:00Z")));
+        assert!(!matches(None));
+    }
+
+    #[test]
+    fn matches_submission_filters() {
+        let overdue = AssignmentFilter {
+            overdue: true,
+            ..Default::default()
+        };
+        let past = Some("2026-05-01T00:00:00Z");
+        assert!(overdue.matches(&assignment("a", past, &["online_upload"], false), now()));
+        assert!(!overdue.matches(&assignment("a", past, &["online_upload"], true), now()));
+        assert!(!overdue.matches(&assignment("a", past, &["on_paper"], false), now()));
+        assert!(!overdue.matches(&assignment("a", None, &["online_upload"], false), now()));
+
+        let unsubmitted = AssignmentFilter {
+            unsubmitted: true,
+            ..Default::default()
+        };
+        assert!(unsubmitted.matches(&assignment("a", None, &["online_upload"], false), now()));
+        assert!(!unsubmitted.matches(&assignment("a", None, &["none"], false), now()));
+        assert!(!unsubmitted.matches(&assignment("a", None, &[], false), now()));
+
+        let upcoming = AssignmentFilter {
+            upcoming: true,
+            ..Default::default()
+        };
+        assert!(upcoming.matches(
+            &assignment("a", Some("2026-05-11T00:00:00Z"), &[], true),
+            now()
+        ));
+        assert!(!upcoming.matches(&assignment("a", past, &[], true), now()));
+        assert!(!upcoming.matches(&assignment("a", None, &[], true), now()));
+    }
+
+    #[test]
+    fn matches_type_and_search() {
+        let filter = AssignmentFilter {
+            kind: Some("online_upload".to_string()),
+            search: Some(parse_regex("^lab \\d").unwrap()),
+            ..Default::default()
+        };
+        let types = ["online_text_entry", "online_upload"];
+        assert!(filter.matches(&assignment("Lab 3", None, &types, false), now()));
+        assert!(!filter.matches(&assignment("Prelab 3", None, &types, false), now()));
+        assert!(!filter.matches(&assignment("Lab 3", None, &["online_quiz"], false), now()));
+        assert!(AssignmentFilter::default().matches(&assignment("x", None, &[], true), now()));
+    }
+}
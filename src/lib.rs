@@ -1,17 +1,38 @@
 extern crate rpassword;
 extern crate serde_json;
-use data::{CommitData, UploadData};
+pub use cli::Cli;
+use cli::{AssignmentFilter, Command, OutputFormat};
+pub use client::{BlockingCanvasClient, CanvasClient, Idempotency, RetryPolicy};
+use credentials::Credentials;
+pub use error::CanvasError;
 use reqwest::multipart;
-mod api_calls;
+use resolve::Inexact;
+use staging::{StagedFile, StagingIndex};
+mod aliases;
+mod cache;
+mod cli;
+mod client;
+mod completions;
+mod credentials;
 mod data;
-mod help;
-use chrono::prelude::*;
+mod dates;
+mod editor;
+mod error;
+mod filters;
+mod oauth;
+mod output;
+mod preflight;
+mod profiles;
+mod resolve;
+mod staging;
+mod state;
+mod todo;
+mod wizard;
 use colored::Colorize;
-use reqwest::header::{HeaderMap, AUTHORIZATION};
 use rpassword::read_password;
-use std::env;
-use std::fs::{canonicalize, metadata, File};
-use std::io::{self, Write};
+use std::fs::{canonicalize, metadata};
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
 
 /*
 Plan for building the Canvas CLI
@@ -36,137 +57,156 @@ Plan for building the Canvas CLI
     - this will submit the file/files to the course as well as add a comment
  */
 
-// Gets the command line input
-pub struct Config {
-    pub command: Option<String>,
-    pub arguments: Vec<String>,
-}
-
-impl Config {
-    pub fn build(args: &[String]) -> Result<Config, &'static str> {
-        if args.len() < 2 {
-            println!("{}", help::help_message());
-            return Err("Invalid Number of Arguments");
+// Will be given the parsed command line and run the matching command
+pub fn run(cli: Cli) -> Result<(), CanvasError> {
+    state::set_profile(cli.profile.as_deref())?;
+    dates::configure(cli.timezone, cli.date_format)?;
+    client::set_verbose(cli.verbose);
+    output::configure(cli.color, cli.columns);
+    cache::configure(cache::CacheSettings {
+        offline: cli.offline,
+        ttl_secs: cli.cache_ttl,
+    });
+    let output = cli.output;
+
+    match cli.command {
+        // Handle: canvas account
+        Command::Account => print_account_info(fetch_account_info(&canvas()?)?, output)?,
+
+        // Handle: canvas courses
+        Command::Courses => print_courses(fetch_courses(&canvas()?)?, output)?,
+
+        // Handle: canvas assignments [<course>] [--jobs <n>] [filters]
+        Command::Assignments {
+            course: Some(course),
+            filter,
+            ..
+        } => {
+            let canvas = canvas()?;
+            let course_id = resolve::course_id(&canvas, &course, Inexact::Note)?;
+            print_assignments(fetch_assignments(&canvas, &course_id, &filter)?, output)?
+        }
+        Command::Assignments {
+            course: None,
+            jobs,
+            filter,
+        } => print_all_assignments(fetch_all_assignments(&canvas()?, jobs, &filter)?, output)?,
+
+        // Handle: canvas todo [--days <n>]
+        Command::Todo { days } => todo::print_todo(todo::fetch_todo(&canvas()?, days)?, output)?,
+
+        // Handle: canvas login
+        Command::Login {
+            oauth,
+            client_id,
+            client_secret,
+            redirect_port,
+        } => login(oauth, client_id, client_secret, redirect_port)?,
+
+        // Handle: canvas add <file_path>...
+        Command::Add { file_paths } => add_files(&canvas()?, &file_paths)?,
+
+        // Handle: canvas commit
+        Command::Commit { message } => commit_files(&canvas()?, message)?,
+
+        // Handle: canvas status
+        Command::Status => print_status(output)?,
+
+        // Handle: canvas reset [<file_path>...]
+        Command::Reset { file_paths } => reset_files(&file_paths)?,
+
+        // Handle: canvas submit -i [<course>] [<assignment>]
+        Command::Submit {
+            course,
+            assignment,
+            interactive: true,
+            force,
+        } => wizard::run(&canvas()?, course.as_deref(), assignment.as_deref(), force)?,
+
+        // Handle: canvas submit <course> <assignment>
+        Command::Submit {
+            course: Some(course),
+            assignment: Some(assignment),
+            force,
+            ..
+        } => {
+            let canvas = canvas()?;
+            let course_id = resolve::course_id(&canvas, &course, Inexact::Confirm)?;
+            let assignment_id =
+                resolve::assignment_id(&canvas, course_id, &assignment, Inexact::Confirm)?;
+            submit_file(&canvas, &course_id, &assignment_id, force)?
+        }
+        // clap already requires both without --interactive
+        Command::Submit { .. } => {
+            return Err(CanvasError::Usage(
+                "submit needs a course and an assignment, or --interactive".to_string(),
+            ))
         }
-        Ok(Config {
-            command: Some(args[1].clone()), // Gets the first input which should be the command
-            arguments: args[2..].to_vec(), // Gets the rest of the inputs which should be the arguments
-        })
-    }
-}
 
-// Will be given a config struct that will have the command and arguments
-pub fn run(config: Config) -> Result<(), &'static str> {
-    match config.command {
-        // Handle the commands using the run function
-        Some(command) => match command.as_str() {
-            // Handle: canva account
-            "account" => {
-                if config.arguments.len() == 0 {
-                    print_account_info(fetch_account_info().expect("Error getting account info"));
-                } else {
-                    return Err("Too many arguments");
-                }
-            }
-            // Handle: canva courses
-            "courses" => {
-                if config.arguments.len() == 0 {
-                    print_courses(fetch_courses().expect("Error getting courses"));
-                } else {
-                    return Err("Too many arguments");
-                }
-            }
+        // Handle: canvas push <course> <assignment> <file_paths...> [-m <comment>]
+        Command::Push {
+            course,
+            assignment,
+            file_paths,
+            message,
+            force,
+        } => {
+            let canvas = canvas()?;
+            let course_id = resolve::course_id(&canvas, &course, Inexact::Confirm)?;
+            let assignment_id =
+                resolve::assignment_id(&canvas, course_id, &assignment, Inexact::Confirm)?;
+            push_files(
+                &canvas,
+                &course_id,
+                &assignment_id,
+                &file_paths,
+                message.as_deref(),
+                force,
+            )?
+        }
 
-            // Handle: canva assignments <course_id>
-            "assignments" => {
-                if config.arguments.len() == 1 {
-                    if let Ok(assignments) =
-                        fetch_assignments(&config.arguments[0].parse::<i64>().unwrap())
-                    {
-                        print_assignments(assignments);
-                    } else {
-                        return Err("Error: Invalid Course ID");
-                    }
-                } else {
-                    return Err("Too many arguments");
-                }
-            }
+        // Handle: canvas migrate
+        Command::Migrate => state::migrate_legacy()?,
 
-            // Handle canva login
-            "login" => {
-                if config.arguments.len() == 0 {
-                    login().expect("Error logging in");
-                } else {
-                    return Err("Too many arguments");
-                }
-            }
+        // Handle: canvas alias set|list|remove
+        Command::Alias { command } => aliases::run(command, output)?,
 
-            // Handle: canva add <file_path>
-            "add" => {
-                if config.arguments.len() < 1 {
-                    return Err("Must provide a file path");
-                }
-                add_file(&config.arguments[0]).expect("Error adding file");
-            }
+        // Handle: canvas profile list|use|remove
+        Command::Profile { command } => profiles::run(command, output)?,
 
-            // Handle: canva commit
-            "commit" => {
-                if config.arguments.len() > 0 {
-                    return Err("Too many arguments");
-                }
-                commit_file().expect("Error committing file");
-            }
+        // Handle: canvas completions <shell>
+        Command::Completions { shell } => completions::write_completions(shell)?,
 
-            // Handle: canva submit
-            "submit" => {
-                if config.arguments.len() != 2 {
-                    return Err("Must provide a course and assignment id");
-                }
-                submit_file(
-                    &config.arguments[0].parse::<i64>().unwrap(),
-                    &config.arguments[1].parse::<i64>().unwrap(),
-                )
-                .expect("Error submitting file");
-            }
-
-            // Handle: canva help
-            "help" => println!("{}", help::help_message()),
-            _ => println!(
-                "{} {} {}",
-                "Command not found: ".red(),
-                command,
-                help::help_message()
-            ),
-        },
-        None => println!("{}", "Must Enter A Command!".red()),
+        // Handle: canvas manpage [--dir <dir>]
+        Command::Manpage { dir } => completions::write_manpages(dir.as_deref())?,
     }
     Ok(())
 }
 
+// Client for the Canvas instance and token saved by `canvas login`
+fn canvas() -> Result<BlockingCanvasClient, CanvasError> {
+    BlockingCanvasClient::from_profile()
+}
+
 /*
 function: account
 Description: This function will allow the user to login to their canvas account
 Parameters: auth_token
 Return: Result<(), Box<dyn Error>>
  */
-fn fetch_account_info() -> Result<data::Account, &'static str> {
-    // Will abstract this later to just be an api call that takes three params (api_path, data structure that JSON will be mapped to))
-    let api_path = format!("{}/api/v1/users/self", env::var("SCHOOL_BASE_URL").unwrap());
-    let user_account_information: Result<data::Account, &'static str> =
-        api_calls::call_canvas_api(&api_path);
-
-    match user_account_information {
-        Ok(resp) => Ok(resp),
-        Err(_) => Err("Error getting account info"),
-    }
+fn fetch_account_info(canvas: &BlockingCanvasClient) -> Result<data::Account, CanvasError> {
+    canvas.get("/api/v1/users/self")
 }
 
 // TODO: Change Date Created format to be more readable -> will create a function to do this
-fn print_account_info(account_info: data::Account) {
-    println!("Account Info:");
-    println!("Name: {}", account_info.name);
-    println!("ID: {}", account_info.id);
-    println!("Date Created: {}", account_info.created_at);
+fn print_account_info(
+    account_info: data::Account,
+    output: OutputFormat,
+) -> Result<(), CanvasError> {
+    if output == OutputFormat::Table {
+        println!("Account Info:");
+    }
+    output::render_one(&account_info, output)
 }
 
 /*
@@ -175,45 +215,29 @@ Description: This function will allow the user to see what courses they are enro
 Parameters: auth_token -> but not actually required by user
 Return: Result<(), Box<dyn Error>>
  */
-fn fetch_courses() -> Result<Vec<data::ValidCourse>, &'static str> {
-    let api_path = format!(
-        "{}/api/v1/courses?per_page=100",
-        env::var("SCHOOL_BASE_URL").unwrap()
-    );
-    let user_courses: Result<Vec<data::Course>, &'static str> =
-        api_calls::call_canvas_api(&api_path);
+fn fetch_courses(canvas: &BlockingCanvasClient) -> Result<Vec<data::ValidCourse>, CanvasError> {
+    let user_courses: Vec<data::Course> =
+        canvas.get_paginated("/api/v1/courses?per_page=100", None)?;
 
     let mut valid_courses: Vec<data::ValidCourse> = Vec::new();
 
-    valid_courses.extend(user_courses.unwrap().iter().filter_map(|course| {
+    valid_courses.extend(user_courses.iter().filter_map(|course| {
         match (&course.name, &course.course_code) {
             (Some(name), Some(code)) => Some(data::ValidCourse::new(
                 name.clone(),
                 code.clone(),
                 course.id,
+                course.time_zone.clone(),
             )),
             _ => None,
         }
     }));
+    completions::cache_courses(&valid_courses);
     Ok(valid_courses)
 }
 
-fn print_courses(courses: Vec<data::ValidCourse>) {
-    println!(
-        "{0: <25} {1: <50} {2: <10}",
-        "Course Code".blue(),
-        "Course Name".blue(),
-        "Course ID".blue()
-    );
-
-    for course in courses.iter() {
-        println!(
-            "{0: <25} {1: <50} {2: <10}",
-            course.name,
-            course.course_code,
-            course.id.to_string().green()
-        );
-    }
+fn print_courses(courses: Vec<data::ValidCourse>, output: OutputFormat) -> Result<(), CanvasError> {
+    output::render(&courses, output)
 }
 
 /*
@@ -221,240 +245,697 @@ function: assignments
 Description: Will return all the assignments within a course
 Paramters: course_id
  */
-fn fetch_assignments(course_id: &i64) -> Result<Vec<data::ValidAssignment>, &'static str> {
-    let api_path = format!(
-        "{}/api/v1/courses/{}/assignments?per_page=100",
-        env::var("SCHOOL_BASE_URL").unwrap(),
+fn fetch_assignments(
+    canvas: &BlockingCanvasClient,
+    course_id: &i64,
+    filter: &AssignmentFilter,
+) -> Result<Vec<data::ValidAssignment>, CanvasError> {
+    // Only --timezone course needs the course itself, for its time zone
+    let course_zone = if dates::uses_course_zone() {
+        let course: data::Course = canvas
+            .get(&format!("/api/v1/courses/{}", course_id))
+            .map_err(|err| err.or_not_found(format!("Course {} not found", course_id)))?;
+        course.time_zone
+    } else {
+        None
+    };
+    let valid_assignments = canvas.block_on(fetch_course_assignments(
+        canvas.client(),
+        *course_id,
+        course_zone,
+        filter.bucket(),
+    ))?;
+    // Only a complete listing replaces the cached completion candidates
+    if filter.bucket().is_none() {
+        completions::cache_assignments(*course_id, &valid_assignments);
+    }
+    Ok(filter.apply(valid_assignments))
+}
+
+// The assignments of one course in Canvas' order, only those in `bucket` when one is given.
+// Due dates are shown in `course_zone` with --timezone course.
+async fn fetch_course_assignments(
+    client: &CanvasClient,
+    course_id: i64,
+    course_zone: Option<String>,
+    bucket: Option<&str>,
+) -> Result<Vec<data::ValidAssignment>, CanvasError> {
+    let mut api_path = format!(
+        "/api/v1/courses/{}/assignments?per_page=100&include[]=submission",
         course_id
     );
-    let course_assignments: Result<Vec<data::Assignment>, &'static str> =
-        api_calls::call_canvas_api(&api_path);
-
-    let ca: Vec<data::Assignment>;
-    match course_assignments {
-        Ok(resp) => ca = resp,
-        Err(_) => return Err("Invalid Assignment ID"),
-    }
-
-    let mut valid_assignments: Vec<data::ValidAssignment> = Vec::new();
-
-    valid_assignments.extend(ca.iter().filter_map(|assignment| {
-        assignment.due_at.as_ref().and_then(|due_date| {
-            let new_date = DateTime::parse_from_rfc3339(due_date)
-                .unwrap()
-                .format("%m-%d-%Y")
-                .to_string();
-            assignment.name.as_ref().and_then(|name| {
-                assignment
-                    .id
-                    .map(|id| data::ValidAssignment::new(name.clone(), id, new_date.clone()))
-            })
+    if let Some(bucket) = bucket {
+        api_path.push_str(&format!("&bucket={}", bucket));
+    }
+    let ca: Vec<data::Assignment> = client
+        .get_paginated(&api_path, None)
+        .await
+        .map_err(|err| err.or_not_found(format!("Course {} not found", course_id)))?;
+
+    Ok(ca
+        .iter()
+        .filter_map(|assignment| {
+            let due_date = assignment.due_at.as_deref().and_then(|due_at| {
+                dates::parse_reported("due_at", due_at, assignment.name.as_deref())
+            });
+            let due_date =
+                due_date.map(|due_date| dates::localize(due_date, course_zone.as_deref()));
+            data::ValidAssignment::new(assignment, due_date, course_id)
         })
+        .collect())
+}
+
+// A course with its assignments, or with the error that kept them from being fetched
+type CourseAssignments = (
+    data::ValidCourse,
+    Result<Vec<data::ValidAssignment>, CanvasError>,
+);
+
+/*
+function: canva assignments (without a course)
+Description: Fetches the assignments of every course, `jobs` courses at a time. The courses stay in
+    the order `canvas courses` lists them, a course that fails is reported with its error.
+Return: Result<Vec<CourseAssignments>, CanvasError>
+ */
+fn fetch_all_assignments(
+    canvas: &BlockingCanvasClient,
+    jobs: usize,
+    filter: &AssignmentFilter,
+) -> Result<Vec<CourseAssignments>, CanvasError> {
+    let courses = fetch_courses(canvas)?;
+    let bucket = filter.bucket();
+    let fetched = canvas.block_on(canvas.client().fan_out(courses, jobs, |client, course| {
+        fetch_course_assignments(client, course.id, course.time_zone, bucket)
     }));
+    Ok(fetched
+        .into_iter()
+        .map(|(course, assignments)| {
+            let assignments = assignments.map(|assignments| {
+                if bucket.is_none() {
+                    completions::cache_assignments(course.id, &assignments);
+                }
+                filter.apply(assignments)
+            });
+            (course, assignments)
+        })
+        // Courses with nothing matching the filters are left out of the listing
+        .filter(|(_, assignments)| {
+            !filter.narrows() || assignments.as_ref().map_or(true, |a| !a.is_empty())
+        })
+        .collect())
+}
 
-    Ok(valid_assignments)
+fn print_assignments(
+    assignments: Vec<data::ValidAssignment>,
+    output: OutputFormat,
+) -> Result<(), CanvasError> {
+    output::render(&assignments, output)
 }
-fn print_assignments(assignments: Vec<data::ValidAssignment>) {
-    println!(
-        "{0: <40} {1: <20} {2: <10}",
-        "Assignment Name".blue(),
-        "Due Date".blue(),
-        "Assignment ID".blue()
-    );
 
-    for assignment in assignments.iter() {
-        println!(
-            "{0: <40} {1: <20} {2: <10}",
-            assignment.name,
-            assignment.due_at,
-            assignment.id.to_string().green()
-        );
+// Prints the assignments of every course that could be fetched and a warning for every course that
+// could not, only failing when no course could be fetched at all
+fn print_all_assignments(
+    fetched: Vec<CourseAssignments>,
+    output: OutputFormat,
+) -> Result<(), CanvasError> {
+    let total = fetched.len();
+    let mut assignments: Vec<(data::ValidCourse, Vec<data::ValidAssignment>)> = Vec::new();
+    let mut first_error = None;
+    for (course, result) in fetched {
+        match result {
+            Ok(course_assignments) => assignments.push((course, course_assignments)),
+            Err(err) => {
+                eprintln!(
+                    "{} {} {}: {}",
+                    "Could not fetch the assignments of".yellow(),
+                    course.course_code,
+                    course.name,
+                    err
+                );
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+    if let Some(err) = first_error.filter(|_| assignments.is_empty() && total > 0) {
+        return Err(err);
+    }
+
+    // The table is grouped by course, every other format gets one flat list
+    if output != OutputFormat::Table {
+        let flat: Vec<data::ValidAssignment> = assignments
+            .into_iter()
+            .flat_map(|(_, course_assignments)| course_assignments)
+            .collect();
+        return output::render(&flat, output);
+    }
+    for (course, course_assignments) in &assignments {
+        output::heading(&format!(
+            "\n{} {}",
+            course.course_code.bold(),
+            course.name.bold()
+        ))?;
+        output::render(course_assignments, output)?;
     }
+    Ok(())
 }
+
 /*
 function: canva add [<file_path>] -> can be multiple files
-Description: This function will allow the user to submit an assignment
-Parameters: course_id, assignment_id, file_path
-Return: Result<(UploadData), Box<dyn Error>>
+Description: Stages files to be submitted by asking Canvas for an upload slot for each of them
+Parameters: file_paths
+Return: Result<(), CanvasError>
 
 - This will be step 1 in uploading a file to canvas.
 - The FileUpload struct will be used to store the response from the API call
+- Adding a file that is already staged replaces it
 
 These are the endpoints that will be used for this function
 https://sit.instructure.com/api/v1/users/self/files
  */
-fn add_file(file_path: &String) -> Result<data::UploadData, Box<dyn std::error::Error>> {
-    let full_file_path = canonicalize(file_path).unwrap();
-    let split_path: Vec<&str> = full_file_path.to_str().unwrap().split("/").collect();
-    let parent_path = &split_path[0..split_path.len() - 1].join("/");
-    let file_name = split_path[split_path.len() - 1];
-    let file_size = metadata(&full_file_path)?.len();
-    let api_path = format!(
-        "{}/api/v1/users/self/files",
-        env::var("SCHOOL_BASE_URL").unwrap(),
-    );
-    // let mut headers = HeaderMap::new();
+fn add_files(canvas: &BlockingCanvasClient, file_paths: &[String]) -> Result<(), CanvasError> {
+    let mut index = StagingIndex::load()?;
+    for file_path in file_paths {
+        let staged = add_file(canvas, file_path)?;
+        println!("{} {}", "Added".green(), file_path);
+        index.stage(staged);
+        index.save()?;
+    }
+    Ok(())
+}
 
-    let form: reqwest::multipart::Form = multipart::Form::new()
-        .text("size", file_size.to_string())
-        .text("parent_folder_path", parent_path.clone())
-        .text("file", file_name.to_string());
+fn add_file(canvas: &BlockingCanvasClient, file_path: &str) -> Result<StagedFile, CanvasError> {
+    let full_file_path =
+        canonicalize(file_path).map_err(error::file_error("read", Path::new(file_path)))?;
+    let file_metadata =
+        metadata(&full_file_path).map_err(error::file_error("read", Path::new(file_path)))?;
+    if !file_metadata.is_file() {
+        return Err(CanvasError::Usage(format!("{} is not a file", file_path)));
+    }
+    // The index keeps paths as text, so a name that is not UTF-8 could not be found again on commit
+    let (Some(parent_path), Some(file_name)) = (
+        full_file_path.parent().and_then(|parent| parent.to_str()),
+        full_file_path.file_name().and_then(|name| name.to_str()),
+    ) else {
+        return Err(CanvasError::Usage(format!(
+            "{} is not valid UTF-8, rename it to upload it",
+            full_file_path.display()
+        )));
+    };
+    let parent_path = parent_path.to_string();
+    let file_size = file_metadata.len();
+
+    let form = || {
+        multipart::Form::new()
+            .text("size", file_size.to_string())
+            .text("parent_folder_path", parent_path.clone())
+            .text("file", file_name.to_string())
+    };
 
-    let file_upload_data: Result<data::FileUpload, &'static str> =
-        api_calls::post_data_api(&api_path, form);
+    let file_upload_data: data::FileUpload =
+        canvas.post_form("/api/v1/users/self/files", Idempotency::Idempotent, form)?;
 
     let upload_json = data::UploadData::new(
-        file_upload_data.unwrap(),
+        file_upload_data,
         file_name.to_string(),
         parent_path.to_string(),
     );
-    serde_json::to_writer(
-        &File::create("src/secrets/.upload_data.json")?,
-        &upload_json,
-    )?;
-
-    // println!("{:?}", upload_json);
-
-    Ok(upload_json)
+    Ok(StagedFile::new(
+        full_file_path.to_string_lossy().into_owned(),
+        upload_json,
+    ))
 }
 
 /*
-function: canva commit
-Description: This function will commit the file to canvas
-Paramters: None
-return: Result<(CommitData), Box<dyn Error>>
+function: canva commit [-m <comment>]
+Description: Uploads every staged file to Canvas, keeping the file ids for submit. The comment replaces
+    the one kept for the submission; without -m it is written in $EDITOR when there is a terminal.
+Paramters: message
+return: Result<(), CanvasError>
 */
-fn commit_file() -> Result<data::CommitData, Box<dyn std::error::Error>> {
-    let file = File::open("src/secrets/.upload_data.json").expect("File could not be read");
-    let file_upload_data: UploadData = serde_json::from_reader(file).expect("Error reading file");
-
-    let form: reqwest::multipart::Form = multipart::Form::new()
-        .text("parent_folder_path", file_upload_data.parent_path)
-        .text(
-            "content_type",
-            file_upload_data
-                .file_data
-                .upload_params
-                .unwrap()
-                .content_type
-                .unwrap(),
+fn commit_files(canvas: &BlockingCanvasClient, message: Option<String>) -> Result<(), CanvasError> {
+    let mut index = StagingIndex::load()?;
+    if index.staged().next().is_none() {
+        return Err(CanvasError::Config(
+            "Nothing to commit, run `canvas add <file_path>` first".to_string(),
+        ));
+    }
+    let message = match message {
+        Some(message) => Some(message),
+        None if io::stdin().is_terminal() && io::stdout().is_terminal() => {
+            Some(editor::edit_comment(&index)?)
+        }
+        None => None,
+    };
+    if let Some(message) = message {
+        index.set_message(&message);
+    }
+    index.save()?;
+    // Saved after every upload, so a failed one does not lose the file ids of those before it
+    for position in 0..index.files.len() {
+        let staged = &index.files[position];
+        if staged.is_committed() {
+            continue;
+        }
+        let commit_data = commit_file(canvas, staged)?;
+        index.files[position].file_id = commit_data.id;
+        println!(
+            "{} {}",
+            "Committed".green(),
+            staging::display_path(&index.files[position])
+        );
+        index.save()?;
+    }
+    Ok(())
+}
+
+fn commit_file(
+    canvas: &BlockingCanvasClient,
+    staged: &StagedFile,
+) -> Result<data::CommitData, CanvasError> {
+    let file_upload_data = &staged.upload;
+    let missing_upload_info = || CanvasError::Decode {
+        source: staged.path.clone(),
+        path: "file_data.upload_url".to_string(),
+        message: "Canvas did not return an upload url, run `canvas add` again".to_string(),
+    };
+    let upload_url = file_upload_data
+        .file_data
+        .upload_url
+        .as_ref()
+        .ok_or_else(missing_upload_info)?;
+
+    // Canvas wants every upload param echoed back before the file contents
+    let params = file_upload_data
+        .file_data
+        .upload_params
+        .as_ref()
+        .map(|params| params.fields())
+        .unwrap_or_default();
+    let contents = std::fs::read(&staged.path).map_err(error::file_error(
+        "read",
+        Path::new(&staging::display_path(staged)),
+    ))?;
+    let form = || {
+        let mut form: reqwest::multipart::Form = multipart::Form::new();
+        for (name, value) in &params {
+            form = form.text(name.clone(), value.clone());
+        }
+        form.part(
+            "file",
+            multipart::Part::bytes(contents.clone()).file_name(file_upload_data.file_name.clone()),
         )
-        .text("file", file_upload_data.file_name);
+    };
 
-    let commit_data: Result<data::CommitData, &'static str> =
-        api_calls::post_data_api(&file_upload_data.file_data.upload_url.unwrap(), form);
-    serde_json::to_writer(
-        &File::create("src/secrets/.commit_data.json")?,
-        &commit_data,
-    )?;
-    Ok(commit_data.unwrap())
+    let commit_data: data::CommitData =
+        canvas.post_form(upload_url, Idempotency::Idempotent, form)?;
+    if commit_data.id.is_none() {
+        return Err(CanvasError::Decode {
+            source: upload_url.clone(),
+            path: "id".to_string(),
+            message: "missing file id".to_string(),
+        });
+    }
+    Ok(commit_data)
+}
+
+// Hands uploaded files to an assignment as one submission, with an optional comment for the grader
+fn send_submission(
+    canvas: &BlockingCanvasClient,
+    course_id: &i64,
+    assignment_id: &i64,
+    file_ids: &[i64],
+    comment: Option<&str>,
+) -> Result<(), CanvasError> {
+    let form = || {
+        let mut form: reqwest::multipart::Form =
+            multipart::Form::new().text("submission[submission_type]", "online_upload");
+        for file_id in file_ids {
+            form = form.text("submission[file_ids][]", file_id.to_string());
+        }
+        if let Some(comment) = comment {
+            form = form.text("comment[text_comment]", comment.to_string());
+        }
+        form
+    };
+
+    let submission_path = format!(
+        "/api/v1/courses/{}/assignments/{}/submissions",
+        course_id, assignment_id
+    );
+
+    let _submission: serde_json::Value = canvas
+        .post_form(&submission_path, Idempotency::NotIdempotent, form)
+        .map_err(|err| {
+            err.or_not_found(format!(
+                "Assignment {} not found in course {}",
+                assignment_id, course_id
+            ))
+        })?;
+    // The cached listings still show the assignment as unsubmitted
+    canvas.forget(&format!("/api/v1/courses/{}/assignments", course_id));
+    canvas.forget("/api/v1/planner/items");
+    canvas.forget("/api/v1/users/self/todo");
+    Ok(())
 }
 
 /*
-function: canva submit <course_id> <assignment_id>
-Description: This function will submit the file to canvas
-Parameters: course_id, assignment_id
-Return: Result<(SubmissionData), Box<dyn Error>>
-*/
-#[tokio::main]
-async fn submit_file(
+function: canva push <course> <assignment> <file_paths...> [-m <comment>]
+Description: add, commit and submit in one go, for only the given files. They go through the index
+    like with the separate commands, so when nothing else was staged a failed submission can be retried
+    with `canvas submit`; once submitted they are taken out of it again. Anything staged before is left alone, and when
+    adding or uploading fails the index is put back the way it was.
+ */
+fn push_files(
+    canvas: &BlockingCanvasClient,
     course_id: &i64,
     assignment_id: &i64,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let file = File::open("src/secrets/.commit_data.json").expect("File could not be read");
-    let file_upload_data: CommitData = serde_json::from_reader(file).expect("Error reading file");
-    let form: reqwest::multipart::Form = multipart::Form::new()
-        .text("submission[submission_type]", "online_upload")
-        .text(
-            "submission[file_ids][]",
-            file_upload_data.id.unwrap().to_string(),
-        );
+    file_paths: &[String],
+    comment: Option<&str>,
+    force: bool,
+) -> Result<(), CanvasError> {
+    // Checked before uploading anything the assignment would not take
+    preflight::check(canvas, *course_id, *assignment_id, file_paths, force)?;
+    push_checked(canvas, course_id, assignment_id, file_paths, comment)
+}
+
+// push without the pre-flight check, for callers that already ran it (`canvas submit -i`)
+fn push_checked(
+    canvas: &BlockingCanvasClient,
+    course_id: &i64,
+    assignment_id: &i64,
+    file_paths: &[String],
+    comment: Option<&str>,
+) -> Result<(), CanvasError> {
+    let mut index = StagingIndex::load()?;
+    let mut pushed: Vec<String> = Vec::new();
+    let mut replaced: Vec<StagedFile> = Vec::new();
+    if let Err(err) = upload_pushed(canvas, &mut index, file_paths, &mut pushed, &mut replaced) {
+        // Nothing was submitted, so the index goes back to how it was before the push
+        index.files.retain(|file| !pushed.contains(&file.path));
+        index.files.extend(replaced);
+        index.save()?;
+        return Err(err);
+    }
 
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        AUTHORIZATION,
-        format!("Bearer {}", env::var("CANVAS_AUTH_TOKEN").unwrap())
-            .parse()
-            .unwrap(),
+    println!("{} Submitting", "[3/3]".bold());
+    let file_ids: Vec<i64> = index
+        .files
+        .iter()
+        .filter(|file| pushed.contains(&file.path))
+        .filter_map(|file| file.file_id)
+        .collect();
+    // Kept with the files, so `canvas submit` sends the same comment if this fails
+    let previous_message = index.message.take();
+    index.set_message(comment.unwrap_or_default());
+    index.save()?;
+    if let Err(err) = send_submission(canvas, course_id, assignment_id, &file_ids, comment) {
+        if index.files.iter().all(|file| pushed.contains(&file.path)) {
+            eprintln!(
+                "The files stay committed, retry with `canvas submit {} {}`",
+                course_id, assignment_id
+            );
+        } else {
+            // submit would send the other files in the index along, and their comment is not this one
+            index.message = previous_message;
+            index.save()?;
+            eprintln!("Nothing was submitted, retry by running the same `canvas push` again");
+        }
+        return Err(err);
+    }
+
+    index.files.retain(|file| !pushed.contains(&file.path));
+    index.message = previous_message;
+    index.save()?;
+    println!(
+        "{}",
+        format!("{} file(s) submitted successfully!", file_ids.len()).green()
     );
+    Ok(())
+}
 
-    let submission_path = format!(
-        "{}/api/v1/courses/{}/assignments/{}/submissions",
-        env::var("SCHOOL_BASE_URL").unwrap(),
+// Steps 1 and 2 of push, noting the paths it staged and the entries staging them replaced
+fn upload_pushed(
+    canvas: &BlockingCanvasClient,
+    index: &mut StagingIndex,
+    file_paths: &[String],
+    pushed: &mut Vec<String>,
+    replaced: &mut Vec<StagedFile>,
+) -> Result<(), CanvasError> {
+    println!("{} Adding {} file(s)", "[1/3]".bold(), file_paths.len());
+    for file_path in file_paths {
+        let staged = add_file(canvas, file_path)?;
+        println!("      {} {}", "Added".green(), file_path);
+        pushed.push(staged.path.clone());
+        replaced.extend(index.stage(staged));
+        index.save()?;
+    }
+
+    println!("{} Uploading to Canvas", "[2/3]".bold());
+    for staged in index
+        .files
+        .iter_mut()
+        .filter(|file| pushed.contains(&file.path))
+    {
+        let commit_data = commit_file(canvas, staged)?;
+        staged.file_id = commit_data.id;
+        println!(
+            "      {} {}",
+            "Committed".green(),
+            staging::display_path(staged)
+        );
+    }
+    index.save()
+}
+
+/*
+function: canva submit <course> <assignment>
+Description: Submits every committed file to the assignment in one submission, after checking the
+    assignment would take them (see preflight.rs)
+Parameters: course_id, assignment_id, force -> submit despite what the check found
+Return: Result<(), CanvasError>
+*/
+fn submit_file(
+    canvas: &BlockingCanvasClient,
+    course_id: &i64,
+    assignment_id: &i64,
+    force: bool,
+) -> Result<(), CanvasError> {
+    let mut index = StagingIndex::load()?;
+    let file_ids: Vec<i64> = index.committed().filter_map(|file| file.file_id).collect();
+    if file_ids.is_empty() {
+        return Err(CanvasError::Config(
+            "Nothing has been committed, run `canvas commit` first".to_string(),
+        ));
+    }
+    let file_names: Vec<String> = index
+        .committed()
+        .map(|file| file.upload.file_name.clone())
+        .collect();
+    preflight::check(canvas, *course_id, *assignment_id, &file_names, force)?;
+    send_submission(
+        canvas,
         course_id,
-        assignment_id
+        assignment_id,
+        &file_ids,
+        index.message.as_deref(),
+    )?;
+
+    index.clear_committed();
+    index.save()?;
+    println!(
+        "{}",
+        format!("{} file(s) submitted successfully!", file_ids.len()).green()
     );
+    if index.staged().next().is_some() {
+        println!("Some files were added but not committed, see `canvas status`");
+    }
+    Ok(())
+}
 
-    let resp = reqwest::Client::new()
-        .post(submission_path)
-        .headers(headers)
-        .multipart(form)
-        .send()
-        .await;
+/*
+function: canva status
+Description: Lists the files waiting to be committed and the ones ready to be submitted
+ */
+fn print_status(output: OutputFormat) -> Result<(), CanvasError> {
+    let index = StagingIndex::load()?;
+    if output != OutputFormat::Table {
+        let entries: Vec<staging::StatusEntry> =
+            index.files.iter().map(staging::StatusEntry::new).collect();
+        return output::render(&entries, output);
+    }
+    if index.files.is_empty() {
+        println!("Nothing staged, use `canvas add <file_path>` to add files");
+        return Ok(());
+    }
+    if index.committed().next().is_some() {
+        println!("Committed, ready for `canvas submit <course> <assignment>`:");
+        for file in index.committed() {
+            println!("    {}", staging::display_path(file).green());
+        }
+    }
+    if let Some(message) = &index.message {
+        println!("Comment for the grader:");
+        println!("    {}", message.replace('\n', "\n    "));
+    }
+    if index.staged().next().is_some() {
+        println!("Added, not yet uploaded with `canvas commit`:");
+        for file in index.staged() {
+            println!("    {}", staging::display_path(file).red());
+        }
+    }
+    Ok(())
+}
 
-    match resp {
-        Ok(_) => println!("{}", "File submitted successfully!".green()),
-        Err(_) => println!("{}", "Error Logging in! Try again".red()),
+/*
+function: canva reset [<file_path>]
+Description: Unstages the given files, or every file when none are given
+ */
+fn reset_files(file_paths: &[String]) -> Result<(), CanvasError> {
+    let mut index = StagingIndex::load()?;
+    if file_paths.is_empty() {
+        index.files.clear();
+        index.message = None;
+    }
+    for file_path in file_paths {
+        if !index.unstage(file_path)? {
+            return Err(CanvasError::Usage(format!(
+                "{} is not staged, see `canvas status`",
+                file_path
+            )));
+        }
     }
+    index.save()?;
     Ok(())
 }
 
-// Helper function for login to write the user info to the .env file
-fn write_to_env(auth_token: &String, school_url: &String) {
-    let path = std::path::Path::new(".env");
-    let display = path.display();
+// Helper function for login to save the school url in the profile's config directory
+// and the token in the keyring (or an encrypted file)
+fn save_credentials(secret: &str, school_url: &str, oauth: bool) -> Result<(), CanvasError> {
+    let previous = Credentials::load_saved()?;
+    let token_store = credentials::save_token(secret)?;
+    if let Some(previous_store) = previous.token_store.filter(|store| *store != token_store) {
+        credentials::open_store(previous_store)?.delete()?;
+    }
+    let credentials = Credentials {
+        school_base_url: Some(school_url.to_string()),
+        token_store: Some(token_store),
+        oauth,
+        // Logging in again keeps the profile's settings
+        time_zone: previous.time_zone,
+        ..Credentials::default()
+    };
+    credentials.save()?;
+    Ok(())
+}
 
-    let mut env_file = match File::create(&path) {
-        Err(why) => panic!("couldn't create {}: {}", display, why),
-        Ok(file) => file,
+/*
+function: normalize_school_url
+Description: Turns what the user typed for their school into the base url every api path is appended to.
+    "school.instructure.com/\n" becomes "https://school.instructure.com"
+Return: Result<String, CanvasError>, a Usage error when it is not an http(s) url with a host
+ */
+fn normalize_school_url(input: &str) -> Result<String, CanvasError> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(CanvasError::Usage(
+            "School URL can not be empty".to_string(),
+        ));
+    }
+    let with_scheme = if input.contains("://") {
+        input.to_string()
+    } else {
+        format!("https://{}", input)
     };
-    match env_file.write_all(format!("CANVAS_AUTH_TOKEN={}\n", auth_token).as_bytes()) {
-        Err(why) => panic!("couldn't write to {}: {}", display, why),
-        Ok(_) => (),
+    let invalid =
+        |reason: &str| CanvasError::Usage(format!("Invalid school URL {}: {}", input, reason));
+    let url = reqwest::Url::parse(&with_scheme).map_err(|err| invalid(&err.to_string()))?;
+    if url.scheme() != "https" && url.scheme() != "http" {
+        return Err(invalid("it has to start with https://"));
+    }
+    if url.host_str().is_none_or(str::is_empty) {
+        return Err(invalid("it has no host"));
     }
-    match env_file.write_all(format!("SCHOOL_BASE_URL={}\n", school_url).as_bytes()) {
-        Err(why) => panic!("couldn't write to {}: {}", display, why),
-        Ok(_) => (),
+    if url.query().is_some() || url.fragment().is_some() {
+        return Err(invalid("it can not have a query or fragment"));
     }
-    println!("\n{}", "Successfully logged in!".green())
+    if url.scheme() == "http" {
+        eprintln!(
+            "{}",
+            "Warning: the school URL is not https, your token will be sent unencrypted".yellow()
+        );
+    }
+    // Pasting a link to the API itself is fine too, the base url is what is in front of /api/v1
+    let path = url.path().trim_end_matches('/');
+    let path = path.strip_suffix("/api/v1").unwrap_or(path);
+    Ok(format!("{}{}", url.origin().ascii_serialization(), path))
 }
 
-// Change this function to take 0 parameters and then prompt the user for the auth token and school name -> censor the auth token using
 /*
 function: login
-Description: This function will allow the user to login to their canvas account
-Parameters: auth_token
-Return: Result<(), Box<dyn Error>>
+Description: This function will allow the user to login to their canvas account, either with an access
+    token generated in their Canvas settings or through the browser with OAuth2 (--oauth).
+    Nothing is saved unless Canvas accepts the token for /api/v1/users/self.
+Parameters: oauth, client_id, client_secret, redirect_port -> settings for the OAuth2 flow
+Return: Result<(), CanvasError>
  */
-#[tokio::main]
-async fn login() -> Result<(), Box<dyn std::error::Error>> {
-    // Get environment path where auth and school url will be stored
-
-    let mut school_url = String::new();
-    print!("Enter Canvas School URL: ");
-    io::stdout().flush().unwrap();
-    std::io::stdin().read_line(&mut school_url).unwrap();
-    print!("\nEnter Canvas Auth Token: ");
-    std::io::stdout().flush().unwrap();
-    let auth_token = read_password().unwrap();
-    // Need to use the variables as the .env file is not flushed and will cause errors
-    let api_path = format!("{}/api/v1/courses", school_url);
-    let mut headers = HeaderMap::new();
-
-    headers.insert(
-        AUTHORIZATION,
-        format!("Bearer {}", auth_token).parse().unwrap(),
-    );
-    let resp = reqwest::Client::new()
-        .get(api_path.as_str())
-        .headers(headers)
-        .send()
-        .await;
-
-    match resp {
-        Ok(_) => write_to_env(&auth_token, &school_url),
-        Err(_) => println!("{}", "Error Logging in! Try again".red()),
-    }
+fn login(
+    oauth: bool,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+    redirect_port: u16,
+) -> Result<(), CanvasError> {
+    let school_url = normalize_school_url(&prompt("Enter Canvas School URL: ")?)?;
+
+    let (secret, access_token) = if oauth {
+        let client_id = match client_id {
+            Some(client_id) => client_id,
+            None => prompt("Enter OAuth Client ID: ")?,
+        };
+        let client_secret = match client_secret {
+            Some(client_secret) => client_secret,
+            None => {
+                print!("Enter OAuth Client Secret: ");
+                io::stdout().flush()?;
+                read_password()?
+            }
+        };
+        let oauth_login = oauth::OAuthLogin {
+            school_url: &school_url,
+            client_id: &client_id,
+            client_secret: &client_secret,
+            redirect_port,
+        };
+        let token = client::block_on(oauth::authorize(&client::http_client()?, &oauth_login))??;
+        let secret = serde_json::to_string(&token).map_err(|err| CanvasError::Io(err.into()))?;
+        (secret, token.access_token)
+    } else {
+        print!("Enter Canvas Auth Token: ");
+        io::stdout().flush()?;
+        let auth_token = read_password()?.trim().to_string();
+        if auth_token.is_empty() {
+            return Err(CanvasError::Usage(
+                "Auth token can not be empty".to_string(),
+            ));
+        }
+        (auth_token.clone(), auth_token)
+    };
 
+    let canvas = BlockingCanvasClient::new(CanvasClient::with_token(&school_url, &access_token)?)?;
+    let account: data::Account = canvas.get("/api/v1/users/self")?;
+    save_credentials(&secret, &school_url, oauth)?;
+    println!(
+        "{} {} ({})",
+        "Successfully logged in as".green(),
+        account.name,
+        school_url
+    );
     Ok(())
 }
+
+fn prompt(message: &str) -> Result<String, CanvasError> {
+    let mut answer = String::new();
+    print!("{}", message);
+    io::stdout().flush()?;
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().to_string())
+}
@@ -1,18 +1,16 @@
-use canvas_cli::{run, Config};
-use dotenv::dotenv;
-use std::env;
+use canvas_cli::{run, Cli};
+use clap::{CommandFactory, Parser};
+use clap_complete::CompleteEnv;
 use std::process;
 
 fn main() {
-    dotenv().ok();
-    let args = env::args().collect::<Vec<String>>();
-    let configuration = Config::build(&args).unwrap_or_else(|err| {
-        println!("Problem parsing arguments: {}", err);
-        std::process::exit(1);
-    });
+    // Answers the shell when it asks for completions, otherwise does nothing
+    CompleteEnv::with_factory(Cli::command).complete();
 
-    if let Err(e) = run(configuration) {
-        println!("Application error: {}", e);
-        process::exit(1);
+    let cli = Cli::parse();
+
+    if let Err(e) = run(cli) {
+        eprintln!("Application error: {}", e);
+        process::exit(e.exit_code());
     }
 }
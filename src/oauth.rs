@@ -0,0 +1,347 @@
+use crate::credentials::Credentials;
+use crate::error::{self, CanvasError};
+use chrono::Utc;
+use rand::RngCore;
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+// Canvas OAuth2 authorization code flow:
+// https://canvas.instructure.com/doc/api/file.oauth_endpoints.html
+
+// Refresh this long before Canvas says the access token expires, so it does not expire mid request
+const EXPIRY_MARGIN_SECS: i64 = 60;
+
+// What gets saved in the credential store after `canvas login --oauth`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAuthToken {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    // Unix timestamp the access token stops working at
+    pub expires_at: Option<i64>,
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+impl OAuthToken {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| Utc::now().timestamp() >= expires_at - EXPIRY_MARGIN_SECS)
+    }
+}
+
+// Response of /login/oauth2/token, refresh_token is only sent for the authorization_code grant
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: Option<i64>,
+}
+
+pub struct OAuthLogin<'a> {
+    pub school_url: &'a str,
+    pub client_id: &'a str,
+    pub client_secret: &'a str,
+    // Port of the loopback redirect listener, 0 picks a free one
+    pub redirect_port: u16,
+}
+
+/*
+function: authorize
+Description: Sends the user to Canvas to approve access, waits for Canvas to redirect back to a
+    listener on localhost with the authorization code and trades the code for tokens
+Return: the new token, not saved yet
+ */
+pub async fn authorize(
+    http: &reqwest::Client,
+    login: &OAuthLogin<'_>,
+) -> Result<OAuthToken, CanvasError> {
+    let listener = TcpListener::bind(("127.0.0.1", login.redirect_port)).await?;
+    let redirect_uri = format!(
+        "http://127.0.0.1:{}/callback",
+        listener.local_addr()?.port()
+    );
+    let state = random_state();
+
+    let auth_url = Url::parse_with_params(
+        &format!("{}/login/oauth2/auth", login.school_url),
+        &[
+            ("client_id", login.client_id),
+            ("response_type", "code"),
+            ("redirect_uri", redirect_uri.as_str()),
+            ("state", state.as_str()),
+        ],
+    )
+    .map_err(|err| CanvasError::Usage(format!("Invalid school url: {}", err)))?;
+
+    println!(
+        "Open this url to allow Canvas CLI to access your account:\n\n    {}\n",
+        auth_url
+    );
+    open_browser(auth_url.as_str());
+
+    let code = wait_for_code(&listener, &state).await?;
+    let response = request_token(
+        http,
+        login.school_url,
+        &[
+            ("grant_type", "authorization_code"),
+            ("client_id", login.client_id),
+            ("client_secret", login.client_secret),
+            ("redirect_uri", redirect_uri.as_str()),
+            ("code", code.as_str()),
+        ],
+    )
+    .await?;
+
+    Ok(OAuthToken {
+        access_token: response.access_token,
+        refresh_token: response.refresh_token,
+        expires_at: expires_at(response.expires_in),
+        client_id: login.client_id.to_string(),
+        client_secret: login.client_secret.to_string(),
+    })
+}
+
+/*
+function: refresh
+Description: Trades the refresh token for a new access token and saves it in the credential store
+ */
+pub async fn refresh(
+    http: &reqwest::Client,
+    credentials: &Credentials,
+    token: &OAuthToken,
+) -> Result<OAuthToken, CanvasError> {
+    let refresh_token = token
+        .refresh_token
+        .as_deref()
+        .ok_or(CanvasError::Unauthorized)?;
+    let response = request_token(
+        http,
+        credentials.school_base_url()?,
+        &[
+            ("grant_type", "refresh_token"),
+            ("client_id", token.client_id.as_str()),
+            ("client_secret", token.client_secret.as_str()),
+            ("refresh_token", refresh_token),
+        ],
+    )
+    .await
+    .map_err(|err| match err {
+        // Canvas answers 400 invalid_grant once the refresh token itself was revoked
+        CanvasError::Status { status, .. } if status == StatusCode::BAD_REQUEST => {
+            CanvasError::Unauthorized
+        }
+        err => err,
+    })?;
+
+    let refreshed = OAuthToken {
+        access_token: response.access_token,
+        refresh_token: response
+            .refresh_token
+            .or_else(|| token.refresh_token.clone()),
+        expires_at: expires_at(response.expires_in),
+        client_id: token.client_id.clone(),
+        client_secret: token.client_secret.clone(),
+    };
+    credentials.save_oauth_token(&refreshed)?;
+    Ok(refreshed)
+}
+
+async fn request_token(
+    http: &reqwest::Client,
+    school_url: &str,
+    form: &[(&str, &str)],
+) -> Result<TokenResponse, CanvasError> {
+    let resp = http
+        .post(format!("{}/login/oauth2/token", school_url))
+        .form(form)
+        .send()
+        .await?;
+    let status = resp.status();
+    let url = resp.url().to_string();
+    let body = resp.text().await?;
+    if !status.is_success() {
+        return Err(CanvasError::from_response(status, &url, &body));
+    }
+    error::decode(&url, &body)
+}
+
+fn expires_at(expires_in: Option<i64>) -> Option<i64> {
+    expires_in.map(|seconds| Utc::now().timestamp() + seconds)
+}
+
+fn random_state() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Best effort, the url is printed anyway for when there is no browser to open
+fn open_browser(url: &str) {
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(windows) {
+        "explorer"
+    } else {
+        "xdg-open"
+    };
+    std::process::Command::new(opener)
+        .arg(url)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .ok();
+}
+
+// Serves the redirect until Canvas sends the authorization code (or an error) for our state
+async fn wait_for_code(listener: &TcpListener, state: &str) -> Result<String, CanvasError> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).await?;
+
+        // GET /callback?code=...&state=... HTTP/1.1
+        let target = request_line.split_whitespace().nth(1).unwrap_or("/");
+        let url = match Url::parse(&format!("http://127.0.0.1{}", target)) {
+            Ok(url) if url.path() == "/callback" => url,
+            _ => {
+                respond(reader.get_mut(), "404 Not Found", "Not found").await?;
+                continue;
+            }
+        };
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+        };
+        if param("state").as_deref() != Some(state) {
+            respond(
+                reader.get_mut(),
+                "400 Bad Request",
+                "Unexpected login request",
+            )
+            .await?;
+            continue;
+        }
+        if let Some(error) = param("error") {
+            respond(
+                reader.get_mut(),
+                "200 OK",
+                "Login was cancelled, you can close this window",
+            )
+            .await?;
+            return Err(CanvasError::Usage(format!(
+                "Canvas did not allow the login: {}",
+                error
+            )));
+        }
+        if let Some(code) = param("code") {
+            respond(
+                reader.get_mut(),
+                "200 OK",
+                "Logged in to Canvas CLI, you can close this window",
+            )
+            .await?;
+            return Ok(code);
+        }
+        respond(
+            reader.get_mut(),
+            "400 Bad Request",
+            "Missing authorization code",
+        )
+        .await?;
+    }
+}
+
+async fn respond(
+    stream: &mut tokio::net::TcpStream,
+    status: &str,
+    message: &str,
+) -> Result<(), CanvasError> {
+    let body = format!("<html><body><p>{}</p></body></html>", message);
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await.ok();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpStream;
+
+    // Sends a GET to the redirect listener like a browser would, returning the status line
+    async fn visit(port: u16, target: &str) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n", target);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response.lines().next().unwrap_or_default().to_string()
+    }
+
+    #[tokio::test]
+    async fn waits_for_the_code_with_our_state() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let browser = tokio::spawn(async move {
+            vec![
+                visit(port, "/favicon.ico").await,
+                visit(port, "/callback?code=stolen&state=other").await,
+                visit(port, "/callback?state=abc").await,
+                visit(port, "/callback?code=xyz&state=abc").await,
+            ]
+        });
+        let code = wait_for_code(&listener, "abc").await.unwrap();
+        assert_eq!(code, "xyz");
+        assert_eq!(
+            browser.await.unwrap(),
+            vec![
+                "HTTP/1.1 404 Not Found",
+                "HTTP/1.1 400 Bad Request",
+                "HTTP/1.1 400 Bad Request",
+                "HTTP/1.1 200 OK"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn fails_when_the_login_is_denied() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let browser =
+            tokio::spawn(
+                async move { visit(port, "/callback?error=access_denied&state=abc").await },
+            );
+        let result = wait_for_code(&listener, "abc").await;
+        assert!(
+            matches!(result, Err(CanvasError::Usage(message)) if message.contains("access_denied"))
+        );
+        assert_eq!(browser.await.unwrap(), "HTTP/1.1 200 OK");
+    }
+
+    #[test]
+    fn expires_a_minute_early() {
+        let token = |expires_at| OAuthToken {
+            access_token: "a".to_string(),
+            refresh_token: None,
+            expires_at,
+            client_id: "c".to_string(),
+            client_secret: "s".to_string(),
+        };
+        let now = Utc::now().timestamp();
+        assert!(!token(None).is_expired());
+        assert!(!token(Some(now + 3600)).is_expired());
+        assert!(token(Some(now + 30)).is_expired());
+        assert!(token(Some(now - 1)).is_expired());
+    }
+}
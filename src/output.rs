@@ -0,0 +1,433 @@
+use crate::cli::{ColorChoice, OutputFormat};
+use crate::error::CanvasError;
+use colored::Colorize;
+use serde::Serialize;
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::sync::OnceLock;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+// Every listing command hands its records to render(), which prints them in the format picked with
+// --output. JSON, NDJSON and YAML come from the records' Serialize impl, so their field names are the
+// struct's. CSV, TSV and the table come from the records' columns, CSV and TSV headed by the field names.
+// Only the table may show values for people (like dates in the --date-format), the rest stay stable.
+
+// Columns picked with --columns, in the order they were given
+static SELECTED_COLUMNS: OnceLock<Vec<String>> = OnceLock::new();
+
+// Tables are never squeezed below this many characters per column
+const MIN_COLUMN_WIDTH: usize = 8;
+const ELLIPSIS: char = '…';
+
+pub struct Column {
+    // Header in CSV and TSV, the name of the matching serialized field
+    pub field: &'static str,
+    // Header in the table
+    pub title: &'static str,
+    // Shorter names --columns accepts besides the field name
+    pub aliases: &'static [&'static str],
+    pub style: CellStyle,
+}
+
+pub enum CellStyle {
+    Plain,
+    // Ids are what the other commands take, so they stand out in the table
+    Id,
+}
+
+impl Column {
+    pub fn new(field: &'static str, title: &'static str) -> Column {
+        Column {
+            field,
+            title,
+            aliases: &[],
+            style: CellStyle::Plain,
+        }
+    }
+
+    // Ids are never shortened to fit the terminal, a truncated id is useless
+    pub fn id(field: &'static str, title: &'static str) -> Column {
+        Column {
+            style: CellStyle::Id,
+            ..Column::new(field, title)
+        }
+    }
+
+    pub fn alias(mut self, aliases: &'static [&'static str]) -> Column {
+        self.aliases = aliases;
+        self
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.field == name || self.aliases.contains(&name)
+    }
+}
+
+/*
+function: configure
+Description: Applies --color and --columns for everything printed afterwards. With `auto` colors are
+    used when stdout is a terminal and neither NO_COLOR nor CLICOLOR=0 is set.
+ */
+pub fn configure(color: ColorChoice, columns: Option<Vec<String>>) {
+    match color {
+        ColorChoice::Always => colored::control::set_override(true),
+        ColorChoice::Never => colored::control::set_override(false),
+        ColorChoice::Auto => {
+            if !io::stdout().is_terminal() || env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty())
+            {
+                colored::control::set_override(false);
+            }
+        }
+    }
+    if let Some(columns) = columns {
+        SELECTED_COLUMNS.set(columns).ok();
+    }
+}
+
+// Indexes of the columns to print: the ones picked with --columns, or all of them
+fn selected_columns(columns: &[Column]) -> Result<Vec<usize>, CanvasError> {
+    let selected = match SELECTED_COLUMNS.get() {
+        Some(selected) => selected,
+        None => return Ok((0..columns.len()).collect()),
+    };
+    selected
+        .iter()
+        .map(|name| {
+            let name = name.trim().to_lowercase();
+            columns
+                .iter()
+                .position(|column| column.matches(&name))
+                .ok_or_else(|| {
+                    let available: Vec<&str> = columns.iter().map(|column| column.field).collect();
+                    CanvasError::Usage(format!(
+                        "Unknown column {}, this listing has {}",
+                        name,
+                        available.join(", ")
+                    ))
+                })
+        })
+        .collect()
+}
+
+// The header and rows of a listing, reduced to the selected columns
+struct Rows {
+    columns: Vec<Column>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Rows {
+    // `table` picks the values shown in the table over the ones for CSV and TSV
+    fn new<R: Record>(records: &[R], table: bool) -> Result<Rows, CanvasError> {
+        let mut all_columns: Vec<Option<Column>> = R::columns().into_iter().map(Some).collect();
+        let indexes = selected_columns(&R::columns())?;
+        let rows = records
+            .iter()
+            .map(|record| {
+                let values = if table {
+                    record.table_values()
+                } else {
+                    record.values()
+                };
+                indexes.iter().map(|index| values[*index].clone()).collect()
+            })
+            .collect();
+        let columns = indexes
+            .iter()
+            .filter_map(|index| all_columns[*index].take())
+            .collect();
+        Ok(Rows { columns, rows })
+    }
+}
+
+// A row of a listing
+pub trait Record: Serialize {
+    fn columns() -> Vec<Column>;
+    // One value per column, in the same order
+    fn values(&self) -> Vec<String>;
+    // The values as the table shows them, e.g. dates in the --date-format instead of RFC 3339
+    fn table_values(&self) -> Vec<String> {
+        self.values()
+    }
+}
+
+/*
+function: render
+Description: Prints a listing in the given format to stdout
+Parameters: records -> rows of the listing, format -> from --output
+Return: Result<(), CanvasError>, a closed stdout (e.g. piped into head) is not an error
+ */
+pub fn render<R: Record>(records: &[R], format: OutputFormat) -> Result<(), CanvasError> {
+    let rows = Rows::new(records, format == OutputFormat::Table)?;
+    write_stdout(|out| match format {
+        OutputFormat::Table => write_table(out, &rows),
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, records)?;
+            writeln!(out)
+        }
+        OutputFormat::Ndjson => {
+            for record in records {
+                serde_json::to_writer(&mut *out, record)?;
+                writeln!(out)?;
+            }
+            Ok(())
+        }
+        OutputFormat::Csv => write_delimited(out, &rows, b','),
+        OutputFormat::Tsv => write_delimited(out, &rows, b'\t'),
+        OutputFormat::Yaml => serde_yaml::to_writer(out, records).map_err(io::Error::other),
+    })
+}
+
+// A single record, e.g. the account: one "Title: value" line per column in the table,
+// an object instead of a list of them in JSON and YAML
+pub fn render_one<R: Record>(record: &R, format: OutputFormat) -> Result<(), CanvasError> {
+    match format {
+        OutputFormat::Table => {
+            let rows = Rows::new(std::slice::from_ref(record), true)?;
+            write_stdout(|out| {
+                for (column, value) in rows.columns.iter().zip(&rows.rows[0]) {
+                    writeln!(out, "{}: {}", column.title, value)?;
+                }
+                Ok(())
+            })
+        }
+        OutputFormat::Json => write_stdout(|out| {
+            serde_json::to_writer_pretty(&mut *out, record)?;
+            writeln!(out)
+        }),
+        OutputFormat::Yaml => {
+            write_stdout(|out| serde_yaml::to_writer(out, record).map_err(io::Error::other))
+        }
+        _ => render(std::slice::from_ref(record), format),
+    }
+}
+
+// A heading between the tables of a grouped listing, e.g. the course in `canvas assignments`
+pub fn heading(text: &str) -> Result<(), CanvasError> {
+    write_stdout(|out| writeln!(out, "{}", text))
+}
+
+fn write_stdout<F>(write: F) -> Result<(), CanvasError>
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<()>,
+{
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match write(&mut out).and_then(|_| out.flush()) {
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+/*
+function: write_table
+Description: Prints rows as aligned columns. Widths are measured in terminal cells, so wide (e.g. CJK)
+    characters line up too. When the table is wider than the terminal the widest text columns are
+    narrowed and their long cells end in an ellipsis; ids are never shortened.
+ */
+fn write_table(out: &mut dyn Write, rows: &Rows) -> io::Result<()> {
+    let widths = fit_widths(rows, terminal_width());
+    let header: Vec<String> = rows
+        .columns
+        .iter()
+        .zip(&widths)
+        .map(|(column, width)| pad(&column.title.blue().to_string(), column.title, *width))
+        .collect();
+    writeln!(out, "{}", header.join(" ").trim_end())?;
+    for row in &rows.rows {
+        let cells: Vec<String> = rows
+            .columns
+            .iter()
+            .zip(row)
+            .zip(&widths)
+            .map(|((column, value), width)| {
+                let text = ellipsize(value, *width);
+                let styled = match column.style {
+                    CellStyle::Plain => text.clone(),
+                    CellStyle::Id => text.green().to_string(),
+                };
+                pad(&styled, &text, *width)
+            })
+            .collect();
+        writeln!(out, "{}", cells.join(" ").trim_end())?;
+    }
+    Ok(())
+}
+
+// Width of the terminal stdout is printed to, $COLUMNS wins; None when not printing to a terminal
+fn terminal_width() -> Option<usize> {
+    if let Some(columns) = env::var("COLUMNS").ok().and_then(|c| c.parse().ok()) {
+        return Some(columns);
+    }
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+    terminal_size::terminal_size().map(|(width, _)| width.0 as usize)
+}
+
+fn fit_widths(rows: &Rows, available: Option<usize>) -> Vec<usize> {
+    let mut widths: Vec<usize> = rows
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(index, column)| {
+            rows.rows
+                .iter()
+                .map(|row| row[index].width())
+                .chain([column.title.width()])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let available = match available {
+        Some(available) => available,
+        None => return widths,
+    };
+    let separators = widths.len().saturating_sub(1);
+    // Take one cell at a time from the widest column that can still give one up
+    while widths.iter().sum::<usize>() + separators > available {
+        let widest = rows
+            .columns
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .filter(|(_, (column, width))| {
+                matches!(column.style, CellStyle::Plain) && **width > MIN_COLUMN_WIDTH
+            })
+            .max_by_key(|(_, (_, width))| **width)
+            .map(|(index, _)| index);
+        match widest {
+            Some(index) => widths[index] -= 1,
+            None => break,
+        }
+    }
+    widths
+}
+
+// Cuts text to at most `width` terminal cells, ending in an ellipsis when anything was cut
+fn ellipsize(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return text.to_string();
+    }
+    // Not even the ellipsis fits
+    if width == 0 {
+        return String::new();
+    }
+    let mut shortened = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let c_width = c.width().unwrap_or(0);
+        if used + c_width + 1 > width {
+            break;
+        }
+        shortened.push(c);
+        used += c_width;
+    }
+    shortened.push(ELLIPSIS);
+    shortened
+}
+
+// Pads `styled` (which may contain color codes) by how much `text` falls short of `width` cells
+fn pad(styled: &str, text: &str, width: usize) -> String {
+    format!(
+        "{}{}",
+        styled,
+        " ".repeat(width.saturating_sub(text.width()))
+    )
+}
+
+fn write_delimited(out: &mut dyn Write, rows: &Rows, delimiter: u8) -> io::Result<()> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(out);
+    let fields: Vec<&str> = rows.columns.iter().map(|column| column.field).collect();
+    writer.write_record(&fields)?;
+    for row in &rows.rows {
+        writer.write_record(row)?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(columns: Vec<Column>, rows: &[&[&str]]) -> Rows {
+        Rows {
+            columns,
+            rows: rows
+                .iter()
+                .map(|row| row.iter().map(|value| value.to_string()).collect())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn ellipsizes_to_the_width_in_cells() {
+        let cases = [
+            ("Operating Systems", 20, "Operating Systems"),
+            ("Operating Systems", 10, "Operating…"),
+            // A double-width character that would end past the cut is left out whole
+            ("日本語の課題", 6, "日本…"),
+            ("日本語の課題", 5, "日本…"),
+            ("a日本", 4, "a日…"),
+            ("a日本", 3, "a…"),
+            ("👍👍👍", 4, "👍…"),
+            // Columns narrower than the ellipsis
+            ("日本語", 1, "…"),
+            ("👍", 1, "…"),
+            ("abc", 0, ""),
+        ];
+        for (text, width, expected) in cases {
+            let shortened = ellipsize(text, width);
+            assert_eq!(shortened, expected, "{:?} in {}", text, width);
+            assert!(shortened.width() <= width, "{:?} in {}", text, width);
+        }
+    }
+
+    #[test]
+    fn keeps_the_natural_widths_when_they_fit() {
+        let rows = rows(
+            vec![Column::new("name", "Name"), Column::id("id", "ID")],
+            &[&["日本語の課題", "48213"], &["Lab", "1"]],
+        );
+        assert_eq!(fit_widths(&rows, Some(80)), [12, 5]);
+        assert_eq!(fit_widths(&rows, None), [12, 5]);
+    }
+
+    #[test]
+    fn shrinks_the_widest_plain_column_on_a_narrow_terminal() {
+        let rows = rows(
+            vec![
+                Column::new("name", "Name"),
+                Column::new("course", "Course"),
+                Column::id("id", "ID"),
+            ],
+            &[&[
+                "Final project report: a long name",
+                "Operating Systems",
+                "1937281",
+            ]],
+        );
+        // 33 + 17 + 7 and two separators do not fit in 40
+        let widths = fit_widths(&rows, Some(40));
+        assert_eq!(widths.iter().sum::<usize>() + 2, 40);
+        assert_eq!(widths[2], 7);
+        assert_eq!(widths, [16, 15, 7]);
+    }
+
+    #[test]
+    fn never_shrinks_below_the_minimum_or_an_id() {
+        let rows = rows(
+            vec![
+                Column::new("name", "Name"),
+                Column::new("course", "Course"),
+                Column::id("id", "ID"),
+            ],
+            &[&["Final project report", "日本語の課題です", "1937281"]],
+        );
+        assert_eq!(
+            fit_widths(&rows, Some(10)),
+            [MIN_COLUMN_WIDTH, MIN_COLUMN_WIDTH, 7]
+        );
+    }
+}
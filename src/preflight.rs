@@ -0,0 +1,204 @@
+use crate::client::BlockingCanvasClient;
+use crate::data::Assignment;
+use crate::dates;
+use crate::error::CanvasError;
+use crate::filters::NOTHING_TO_SUBMIT;
+use chrono::{DateTime, FixedOffset, Utc};
+use colored::Colorize;
+use std::path::Path;
+
+// Before anything is submitted the assignment is checked for what Canvas would reject, or what would
+// only be noticed after the deadline: the wrong submission type, a lock, used up attempts and file
+// types it does not accept. Every problem is listed, --force turns them into warnings.
+
+/*
+function: check
+Description: Fetches the assignment with our submission and checks the files about to be submitted
+Parameters: file_names -> names of the files, only their extensions are looked at
+Return: Result<(), CanvasError>, Rejected with every problem found unless `force` is set
+ */
+pub fn check(
+    canvas: &BlockingCanvasClient,
+    course_id: i64,
+    assignment_id: i64,
+    file_names: &[String],
+    force: bool,
+) -> Result<(), CanvasError> {
+    // Never from the cache: attempts and locks may have changed since it was filled, e.g. by the
+    // last submission
+    let assignment: Assignment = canvas
+        .get_uncached(&format!(
+            "/api/v1/courses/{}/assignments/{}?include[]=submission",
+            course_id, assignment_id
+        ))
+        .map_err(|err| {
+            err.or_not_found(format!(
+                "Assignment {} not found in course {}",
+                assignment_id, course_id
+            ))
+        })?;
+    let problems = problems(&assignment, file_names);
+    if problems.is_empty() {
+        return Ok(());
+    }
+    if !force {
+        return Err(CanvasError::Rejected(problems));
+    }
+    for problem in problems {
+        eprintln!("{} {}", "Submitting anyway:".yellow(), problem);
+    }
+    Ok(())
+}
+
+fn problems(assignment: &Assignment, file_names: &[String]) -> Vec<String> {
+    let now = Utc::now();
+    let mut problems = Vec::new();
+
+    let types = assignment.submission_types.as_deref().unwrap_or_default();
+    if !types.iter().any(|kind| kind == "online_upload") {
+        let takes_none = types
+            .iter()
+            .all(|kind| NOTHING_TO_SUBMIT.contains(&kind.as_str()));
+        problems.push(if takes_none {
+            "It does not take submissions".to_string()
+        } else {
+            format!(
+                "It takes {} submissions, not file uploads",
+                types.join(", ")
+            )
+        });
+    }
+
+    let unlock_at =
+        date("unlock_at", assignment.unlock_at.as_deref()).filter(|unlock_at| *unlock_at > now);
+    let lock_at = date("lock_at", assignment.lock_at.as_deref()).filter(|lock_at| *lock_at <= now);
+    if let Some(unlock_at) = unlock_at {
+        problems.push(format!("It only opens {}", dates::format(unlock_at)));
+    } else if let Some(lock_at) = lock_at {
+        problems.push(format!("It closed {}", dates::format(lock_at)));
+    } else if assignment.locked_for_user == Some(true) {
+        problems.push(
+            assignment
+                .lock_explanation
+                .clone()
+                .unwrap_or_else(|| "It is locked".to_string()),
+        );
+    }
+
+    // -1 or nothing means unlimited attempts
+    if let Some(allowed) = assignment.allowed_attempts.filter(|allowed| *allowed > 0) {
+        let used = assignment
+            .submission
+            .as_ref()
+            .and_then(|submission| submission.attempt)
+            .unwrap_or(0);
+        if used >= allowed {
+            problems.push(format!("All {} of its attempts are used", allowed));
+        }
+    }
+
+    let extensions = assignment.allowed_extensions.as_deref().unwrap_or_default();
+    for file_name in file_names {
+        if !accepts(Path::new(file_name), extensions) {
+            problems.push(format!(
+                "{} is not one of the file types it takes: .{}",
+                file_name,
+                extensions.join(", .")
+            ));
+        }
+    }
+
+    // Canvas knows about restrictions not listed above, e.g. enrollments that ended
+    if problems.is_empty() && assignment.can_submit == Some(false) {
+        problems.push("Canvas says you can not submit to it".to_string());
+    }
+    problems
+}
+
+// A date of the assignment, localized for showing it; one that can not be read is left out
+fn date(field: &str, value: Option<&str>) -> Option<DateTime<FixedOffset>> {
+    value
+        .and_then(|value| dates::parse(field, value).ok())
+        .map(|date| dates::localize(date, None))
+}
+
+// Whether the file has one of the extensions, any file is fine when there are none
+pub fn accepts(path: &Path, extensions: &[String]) -> bool {
+    if extensions.is_empty() {
+        return true;
+    }
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    extensions
+        .iter()
+        .any(|allowed| allowed.trim_start_matches('.').to_lowercase() == extension)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error;
+    use chrono::Duration;
+
+    fn assignment(json: &str) -> Assignment {
+        error::decode("assignment", json).unwrap()
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn passes_an_open_upload_assignment() {
+        let assignment = assignment(
+            r#"{"submission_types": ["online_upload"], "allowed_extensions": ["rs", ".PDF"],
+                "locked_for_user": false, "allowed_attempts": 2, "submission": {"attempt": 1}}"#,
+        );
+        assert!(problems(&assignment, &names(&["main.rs", "report.pdf"])).is_empty());
+    }
+
+    #[test]
+    fn rejects_a_file_type_it_does_not_take() {
+        let assignment =
+            assignment(r#"{"submission_types": ["online_upload"], "allowed_extensions": ["pdf"]}"#);
+        assert_eq!(
+            problems(
+                &assignment,
+                &names(&["report.pdf", "notes.docx", "Makefile"])
+            ),
+            [
+                "notes.docx is not one of the file types it takes: .pdf",
+                "Makefile is not one of the file types it takes: .pdf",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_an_assignment_past_its_lock_date() {
+        let lock_at = (Utc::now() - Duration::days(1)).to_rfc3339();
+        let assignment = assignment(&format!(
+            r#"{{"submission_types": ["online_upload"], "lock_at": "{}", "locked_for_user": true}}"#,
+            lock_at
+        ));
+        let problems = problems(&assignment, &names(&["main.rs"]));
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("It closed "), "{}", problems[0]);
+    }
+
+    #[test]
+    fn rejects_an_assignment_that_takes_no_uploads() {
+        let cases = [
+            (
+                r#"["online_text_entry", "online_url"]"#,
+                "It takes online_text_entry, online_url submissions, not file uploads",
+            ),
+            (r#"["none"]"#, "It does not take submissions"),
+        ];
+        for (types, expected) in cases {
+            let assignment = assignment(&format!(r#"{{"submission_types": {}}}"#, types));
+            assert_eq!(problems(&assignment, &names(&["main.rs"])), [expected]);
+        }
+    }
+}
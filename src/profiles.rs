@@ -0,0 +1,179 @@
+use crate::cli::{OutputFormat, ProfileCommand};
+use crate::credentials::{CredentialStore, Credentials, KeyringStore, StoreKind};
+use crate::dates;
+use crate::error::{self, CanvasError};
+use crate::output::{self, Column, Record};
+use crate::state::{self, Settings, DEFAULT_PROFILE};
+use colored::Colorize;
+use serde::Serialize;
+use std::fs;
+use std::io::{self, Write};
+
+// `canvas profile` manages the named profiles, each one has its own school url, token, staged files and cache
+
+#[derive(Debug, Serialize)]
+struct ProfileInfo {
+    name: String,
+    school_base_url: Option<String>,
+    default: bool,
+}
+
+impl Record for ProfileInfo {
+    fn columns() -> Vec<Column> {
+        vec![
+            Column::new("name", "Profile"),
+            Column::new("school_base_url", "School URL"),
+            Column::new("default", "Default"),
+        ]
+    }
+
+    fn values(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.school_base_url.clone().unwrap_or_default(),
+            self.default.to_string(),
+        ]
+    }
+}
+
+pub fn run(command: ProfileCommand, output: OutputFormat) -> Result<(), CanvasError> {
+    match command {
+        ProfileCommand::List => list(output),
+        ProfileCommand::Use { name } => use_profile(&name),
+        ProfileCommand::Timezone { zone } => time_zone(zone.as_deref()),
+        ProfileCommand::Remove { name, yes } => remove(&name, yes),
+    }
+}
+
+fn default_profile() -> Result<String, CanvasError> {
+    Ok(Settings::load()?
+        .default_profile
+        .unwrap_or_else(|| DEFAULT_PROFILE.to_string()))
+}
+
+fn exists(profile: &str) -> Result<bool, CanvasError> {
+    Ok(state::list_profiles()?.iter().any(|name| name == profile))
+}
+
+/*
+function: list
+Description: Prints every profile that was logged in with its school url, the default one is marked with *
+ */
+fn list(output: OutputFormat) -> Result<(), CanvasError> {
+    let default = default_profile()?;
+    let mut profiles = Vec::new();
+    for name in state::list_profiles()? {
+        profiles.push(ProfileInfo {
+            school_base_url: Credentials::load_profile(&name)?.school_base_url,
+            default: name == default,
+            name,
+        });
+    }
+
+    if output != OutputFormat::Table {
+        return output::render(&profiles, output);
+    }
+    if profiles.is_empty() {
+        println!("No profiles yet, run `canvas login --profile <name>` to add one");
+        return Ok(());
+    }
+    for profile in profiles {
+        let marker = if profile.default { "*" } else { " " };
+        let school_base_url = profile
+            .school_base_url
+            .unwrap_or_else(|| "(not logged in)".to_string());
+        println!(
+            "{} {:<20} {}",
+            marker.green(),
+            profile.name.bold(),
+            school_base_url
+        );
+    }
+    Ok(())
+}
+
+// Makes `name` the profile used when --profile is not given
+fn use_profile(name: &str) -> Result<(), CanvasError> {
+    state::validate_profile(name)?;
+    if !exists(name)? {
+        return Err(CanvasError::NotFound(format!(
+            "Profile {} not found, run `canvas login --profile {}` first",
+            name, name
+        )));
+    }
+    let mut settings = Settings::load()?;
+    settings.default_profile = (name != DEFAULT_PROFILE).then(|| name.to_string());
+    settings.save()?;
+    println!("{} {}", "Now using profile".green(), name);
+    Ok(())
+}
+
+/*
+function: time_zone
+Description: Prints the time zone dates of the current profile are shown in, or saves a new one.
+    "local" goes back to the local time zone.
+ */
+fn time_zone(zone: Option<&str>) -> Result<(), CanvasError> {
+    let mut credentials = Credentials::load_saved()?;
+    let zone = match zone {
+        Some(zone) => zone.trim(),
+        None => {
+            println!("{}", credentials.time_zone.as_deref().unwrap_or("local"));
+            return Ok(());
+        }
+    };
+    dates::parse_zone(zone).map_err(CanvasError::Usage)?;
+    credentials.time_zone = (zone != "local").then(|| zone.to_string());
+    credentials.save()?;
+    println!(
+        "{} {} now shown in {}",
+        "Dates of profile".green(),
+        state::profile(),
+        zone
+    );
+    Ok(())
+}
+
+/*
+function: remove
+Description: Deletes a profile: its token in the keyring and its config, data and cache directories.
+    Asks first unless --yes is given, removing the default profile makes "default" the default again.
+ */
+fn remove(name: &str, yes: bool) -> Result<(), CanvasError> {
+    state::validate_profile(name)?;
+    if !exists(name)? {
+        return Err(CanvasError::NotFound(format!("Profile {} not found", name)));
+    }
+    if !yes {
+        print!(
+            "Remove profile {} with its token and staged files? [y/N] ",
+            name
+        );
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            println!("Nothing removed");
+            return Ok(());
+        }
+    }
+
+    // token.enc goes with the config directory, only a keyring entry lives somewhere else
+    if Credentials::load_profile(name)?.token_store == Some(StoreKind::Keyring) {
+        KeyringStore::for_profile(name)?.delete()?;
+    }
+    for dir in state::profile_dirs(name)? {
+        match fs::remove_dir_all(&dir) {
+            Ok(()) => (),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+            Err(err) => return Err(error::file_error("delete", &dir)(err)),
+        }
+    }
+    let mut settings = Settings::load()?;
+    if settings.default_profile.as_deref() == Some(name) {
+        settings.default_profile = None;
+        settings.save()?;
+    }
+    println!("{} {}", "Removed profile".green(), name);
+    Ok(())
+}
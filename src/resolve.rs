@@ -0,0 +1,391 @@
+use crate::aliases::Aliases;
+use crate::cli::AssignmentFilter;
+use crate::client::BlockingCanvasClient;
+use crate::data::{ValidAssignment, ValidCourse};
+use crate::error::CanvasError;
+use colored::Colorize;
+use std::io::{self, IsTerminal, Write};
+
+// Commands take courses and assignments the way people remember them. A course is an alias, an id,
+// its code or part of its name; an assignment is an id or part of its name. Names are matched
+// ignoring case: exact matches win over prefixes, prefixes over fragments, fragments over
+// abbreviations (the letters in order, "opsys" for "Operating Systems"). When several match equally
+// well we ask which one was meant, or fail when nobody is there to answer.
+
+// What to do when the only match is not an exact one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Inexact {
+    // Use it and name it on stderr, for commands that only read
+    Note,
+    // Ask first and fail without a terminal, so nothing is submitted to the wrong assignment
+    Confirm,
+}
+
+/*
+function: course_id
+Description: Resolves what was typed for a course to its id. A number is used as an id when it is one
+    of the user's courses, otherwise it has to be a whole part of a course code (392 for CS-392).
+ */
+pub fn course_id(
+    canvas: &BlockingCanvasClient,
+    query: &str,
+    inexact: Inexact,
+) -> Result<i64, CanvasError> {
+    if let Some(alias) = Aliases::load()?.get(query) {
+        return Ok(alias.course_id);
+    }
+    Ok(course(canvas, query, inexact)?.id)
+}
+
+// The course matching what was typed, looked up in the course list even when it is an id or alias
+pub fn course(
+    canvas: &BlockingCanvasClient,
+    query: &str,
+    inexact: Inexact,
+) -> Result<ValidCourse, CanvasError> {
+    let courses = crate::fetch_courses(canvas)?;
+    let query = query.trim();
+    if let Some(alias) = Aliases::load()?.get(query) {
+        return courses
+            .into_iter()
+            .find(|course| course.id == alias.course_id)
+            .ok_or_else(|| {
+                CanvasError::NotFound(format!(
+                    "{} is an alias of course {}, which is not in your course list",
+                    query, alias.course_id
+                ))
+            });
+    }
+    let (matches, exact) = course_matches(query, &courses)?;
+    let index = pick(
+        query,
+        "course",
+        "canvas courses",
+        (&matches, exact),
+        inexact,
+        describe_course,
+    )?;
+    Ok(matches[index].clone())
+}
+
+// The courses that could be meant and whether they matched exactly, an id is always exact
+fn course_matches<'a>(
+    query: &str,
+    courses: &'a [ValidCourse],
+) -> Result<(Vec<&'a ValidCourse>, bool), CanvasError> {
+    if let Some(course) = courses.iter().find(|course| course.id.to_string() == query) {
+        return Ok((vec![course], true));
+    }
+    if is_number(query) {
+        let matches = code_matches(query, courses);
+        if matches.is_empty() {
+            return Err(CanvasError::NotFound(format!(
+                "No course with id {}, see `canvas courses`",
+                query
+            )));
+        }
+        return Ok((matches, false));
+    }
+    Ok(best_matches(query, courses, |course| {
+        vec![
+            course.course_code.clone(),
+            course.name.clone(),
+            format!("{} {}", course.course_code, course.name),
+        ]
+    }))
+}
+
+fn is_number(query: &str) -> bool {
+    !query.is_empty() && query.chars().all(|c| c.is_ascii_digit())
+}
+
+// Courses with the number as a whole part of their code: 392 matches CS-392 and CS392, 9 neither
+fn code_matches<'a>(number: &str, courses: &'a [ValidCourse]) -> Vec<&'a ValidCourse> {
+    courses
+        .iter()
+        .filter(|course| {
+            course
+                .course_code
+                .split(|c: char| !c.is_alphanumeric())
+                .any(|part| {
+                    part == number || part.trim_start_matches(char::is_alphabetic) == number
+                })
+        })
+        .collect()
+}
+
+fn describe_course(course: &ValidCourse) -> String {
+    format!("{} {} ({})", course.course_code, course.name, course.id)
+}
+
+// Resolves what was typed for an assignment of the course to its id
+pub fn assignment_id(
+    canvas: &BlockingCanvasClient,
+    course_id: i64,
+    query: &str,
+    inexact: Inexact,
+) -> Result<i64, CanvasError> {
+    if let Ok(id) = query.trim().parse() {
+        return Ok(id);
+    }
+    let assignments = crate::fetch_assignments(canvas, &course_id, &AssignmentFilter::default())?;
+    let (matches, exact) = best_matches(query.trim(), &assignments, |assignment| {
+        vec![assignment.name.clone()]
+    });
+    let listing = format!("canvas assignments {}", course_id);
+    let index = pick(
+        query,
+        "assignment",
+        &listing,
+        (&matches, exact),
+        inexact,
+        describe_assignment,
+    )?;
+    Ok(matches[index].id)
+}
+
+fn describe_assignment(assignment: &ValidAssignment) -> String {
+    match assignment.due() {
+        Some(due_at) => format!("{}, due {} ({})", assignment.name, due_at, assignment.id),
+        None => format!("{} ({})", assignment.name, assignment.id),
+    }
+}
+
+// How well `query` matches `text`, higher is better and None is no match at all
+fn score(query: &str, text: &str) -> Option<u8> {
+    let query = query.to_lowercase();
+    let text = text.to_lowercase();
+    if text == query {
+        Some(3)
+    } else if text.starts_with(&query) {
+        Some(2)
+    } else if text.contains(&query) {
+        Some(1)
+    } else if is_abbreviation(&query, &text) {
+        Some(0)
+    } else {
+        None
+    }
+}
+
+// Every letter and digit of the query appears in the text, in the same order
+fn is_abbreviation(query: &str, text: &str) -> bool {
+    let mut text = text.chars();
+    query
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .all(|c| text.any(|t| t == c))
+}
+
+// The items matching best in their original order, and whether they matched exactly
+fn best_matches<'a, T, F>(query: &str, items: &'a [T], texts: F) -> (Vec<&'a T>, bool)
+where
+    F: Fn(&T) -> Vec<String>,
+{
+    let scored: Vec<(Option<u8>, &T)> = items
+        .iter()
+        .map(|item| {
+            let best = texts(item)
+                .iter()
+                .filter_map(|text| score(query, text))
+                .max();
+            (best, item)
+        })
+        .collect();
+    let best = scored.iter().filter_map(|(score, _)| *score).max();
+    let matches = scored
+        .into_iter()
+        .filter(|(score, _)| best.is_some() && *score == best)
+        .map(|(_, item)| item)
+        .collect();
+    (matches, best == Some(3))
+}
+
+/*
+function: pick
+Description: The index of the match to use: the only one, or the one picked from a numbered list
+    when stdin is a terminal. Nothing matching and several matching without a terminal are errors.
+    A single inexact match is named on stderr so a typo never goes unnoticed, or has to be confirmed.
+ */
+fn pick<T, F>(
+    query: &str,
+    kind: &str,
+    listing: &str,
+    (matches, exact): (&[&T], bool),
+    inexact: Inexact,
+    describe: F,
+) -> Result<usize, CanvasError>
+where
+    F: Fn(&T) -> String,
+{
+    match matches.len() {
+        0 => {
+            return Err(CanvasError::NotFound(format!(
+                "No {} matches {:?}, see `{}`",
+                kind, query, listing
+            )))
+        }
+        1 if exact => return Ok(0),
+        1 => {
+            let description = describe(matches[0]);
+            match inexact {
+                Inexact::Note => {
+                    eprintln!("{} {}", format!("Using {}", kind).green(), description)
+                }
+                Inexact::Confirm => confirm(query, kind, &description)?,
+            }
+            return Ok(0);
+        }
+        _ => (),
+    }
+    let descriptions: Vec<String> = matches.iter().map(|item| describe(item)).collect();
+    if !io::stdin().is_terminal() {
+        return Err(CanvasError::Usage(format!(
+            "{:?} matches several {}s, use more of the name or the id:\n  {}",
+            query,
+            kind,
+            descriptions.join("\n  ")
+        )));
+    }
+    // The list goes to stderr so it never ends up in piped output
+    eprintln!("{:?} matches several {}s:", query, kind);
+    for (number, description) in descriptions.iter().enumerate() {
+        eprintln!("  {}) {}", number + 1, description);
+    }
+    loop {
+        eprint!("Which one? [1-{}] ", descriptions.len());
+        io::stderr().flush()?;
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer)? == 0 {
+            return Err(CanvasError::Usage(format!("No {} picked", kind)));
+        }
+        match answer.trim().parse::<usize>() {
+            Ok(number) if (1..=descriptions.len()).contains(&number) => return Ok(number - 1),
+            _ => eprintln!("Answer with a number between 1 and {}", descriptions.len()),
+        }
+    }
+}
+
+// Asks whether the only, inexact match is the one meant
+fn confirm(query: &str, kind: &str, description: &str) -> Result<(), CanvasError> {
+    if !io::stdin().is_terminal() {
+        return Err(CanvasError::Usage(format!(
+            "{:?} only loosely matches {} {}, type it exactly or use the id",
+            query, kind, description
+        )));
+    }
+    eprint!(
+        "{:?} is not an exact match, use {} {}? [y/N] ",
+        query, kind, description
+    );
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    match answer.trim().to_lowercase().as_str() {
+        "y" | "yes" => Ok(()),
+        _ => Err(CanvasError::Usage(format!("No {} picked", kind))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn courses() -> Vec<ValidCourse> {
+        [
+            (48213, "CS-392", "Operating Systems"),
+            (48214, "CS-385", "Algorithms"),
+            (51002, "MA-221", "Linear Algebra"),
+            (51003, "MA-222", "Linear Algebra II"),
+        ]
+        .into_iter()
+        .map(|(id, code, name)| ValidCourse::new(name.to_string(), code.to_string(), id, None))
+        .collect()
+    }
+
+    #[test]
+    fn scores_exact_over_prefix_over_fragment_over_abbreviation() {
+        let cases = [
+            ("cs-392", "CS-392", Some(3)),
+            ("operating", "Operating Systems", Some(2)),
+            ("systems", "Operating Systems", Some(1)),
+            ("opsys", "Operating Systems", Some(0)),
+            ("sysop", "Operating Systems", None),
+            ("", "Operating Systems", Some(2)),
+        ];
+        for (query, text, expected) in cases {
+            assert_eq!(score(query, text), expected, "{:?} in {:?}", query, text);
+        }
+    }
+
+    #[test]
+    fn abbreviations_keep_the_order_of_the_letters() {
+        let cases = [
+            ("opsys", "operating systems", true),
+            ("o.s.", "operating systems", true),
+            ("cs392", "cs-392 operating systems", true),
+            ("soy", "operating systems", false),
+            ("opsyss", "operating systems", true),
+            ("opsysz", "operating systems", false),
+        ];
+        for (query, text, expected) in cases {
+            assert_eq!(
+                is_abbreviation(query, text),
+                expected,
+                "{:?} in {:?}",
+                query,
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn resolves_courses() {
+        // The query, the ids it resolves to and whether that was an exact match
+        let cases: [(&str, &[i64], bool); 9] = [
+            ("48213", &[48213], true),
+            ("CS-392", &[48213], true),
+            ("cs-392", &[48213], true),
+            ("opsys", &[48213], false),
+            ("algo", &[48214], false),
+            ("Linear Algebra", &[51002], true),
+            ("linear", &[51002, 51003], false),
+            ("392", &[48213], false),
+            ("22", &[], false),
+        ];
+        let courses = courses();
+        for (query, ids, exact) in cases {
+            let (matches, matched_exactly) = match course_matches(query, &courses) {
+                Ok(result) => result,
+                Err(CanvasError::NotFound(_)) if ids.is_empty() => continue,
+                Err(err) => panic!("{:?}: {}", query, err),
+            };
+            let matched: Vec<i64> = matches.iter().map(|course| course.id).collect();
+            assert_eq!(matched, ids, "{:?}", query);
+            assert_eq!(matched_exactly, exact, "{:?}", query);
+        }
+    }
+
+    #[test]
+    fn a_number_only_matches_a_whole_number_in_a_code() {
+        let courses = courses();
+        assert!(matches!(
+            course_matches("9", &courses),
+            Err(CanvasError::NotFound(message)) if message == "No course with id 9, see `canvas courses`"
+        ));
+        let codes: Vec<&str> = code_matches("221", &courses)
+            .iter()
+            .map(|course| course.course_code.as_str())
+            .collect();
+        assert_eq!(codes, ["MA-221"]);
+    }
+
+    #[test]
+    fn a_tie_is_never_picked_without_asking() {
+        let courses = courses();
+        let (matches, exact) = course_matches("linear", &courses).unwrap();
+        // Inexact and more than one, so pick asks which one was meant or fails without a terminal
+        assert_eq!(matches.len(), 2);
+        assert!(!exact);
+    }
+}
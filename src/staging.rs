@@ -0,0 +1,224 @@
+use crate::data::UploadData;
+use crate::error::{self, CanvasError};
+use crate::output::{Column, Record};
+use crate::state;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+// Works like git's index: `add` stages files, `commit` uploads them to Canvas and
+// `submit` hands every committed file to an assignment in one submission.
+// Saved as index.json in the profile's data directory.
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct StagingIndex {
+    pub files: Vec<StagedFile>,
+    // Comment for the grader from `commit -m`, sent with the next submission
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StagedFile {
+    // Canonical path of the file on disk, used to find it again for reset
+    pub path: String,
+    pub upload: UploadData,
+    // Canvas file id, set once `commit` has uploaded the file
+    pub file_id: Option<i64>,
+}
+
+impl StagedFile {
+    pub fn new(path: String, upload: UploadData) -> StagedFile {
+        StagedFile {
+            path,
+            upload,
+            file_id: None,
+        }
+    }
+
+    pub fn is_committed(&self) -> bool {
+        self.file_id.is_some()
+    }
+}
+
+impl StagingIndex {
+    // An index that was never written is just an empty one
+    pub fn load() -> Result<StagingIndex, CanvasError> {
+        let path = state::data_file("index.json")?;
+        match state::read_optional(&path)? {
+            Some(text) => error::decode(&path.to_string_lossy(), &text),
+            None => Ok(StagingIndex::default()),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), CanvasError> {
+        state::write_json(&state::data_file("index.json")?, self)
+    }
+
+    // Staging a file again replaces the old entry, so it has to be committed again. Returns the
+    // replaced entry.
+    pub fn stage(&mut self, file: StagedFile) -> Option<StagedFile> {
+        let replaced = self
+            .files
+            .iter()
+            .position(|staged| staged.path == file.path)
+            .map(|position| self.files.remove(position));
+        self.files.push(file);
+        replaced
+    }
+
+    // Removes the entry staged from `path`, or the only one with that file name. Returns whether
+    // anything was unstaged; a file name several staged files share is refused rather than
+    // unstaging them all.
+    pub fn unstage(&mut self, path: &str) -> Result<bool, CanvasError> {
+        let full_path = std::fs::canonicalize(path)
+            .map(|full_path| full_path.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| path.to_string());
+        let position = match self.files.iter().position(|staged| {
+            staged.path == full_path || staged.path == path || display_path(staged) == path
+        }) {
+            Some(position) => Some(position),
+            None => {
+                let named: Vec<usize> = (0..self.files.len())
+                    .filter(|&position| self.files[position].upload.file_name == path)
+                    .collect();
+                if named.len() > 1 {
+                    let paths: Vec<String> = named
+                        .iter()
+                        .map(|&position| display_path(&self.files[position]))
+                        .collect();
+                    return Err(CanvasError::Usage(format!(
+                        "{} could be any of {}, give its path",
+                        path,
+                        paths.join(", ")
+                    )));
+                }
+                named.first().copied()
+            }
+        };
+        Ok(position
+            .map(|position| self.files.remove(position))
+            .is_some())
+    }
+
+    pub fn staged(&self) -> impl Iterator<Item = &StagedFile> {
+        self.files.iter().filter(|file| !file.is_committed())
+    }
+
+    pub fn committed(&self) -> impl Iterator<Item = &StagedFile> {
+        self.files.iter().filter(|file| file.is_committed())
+    }
+
+    // After a submission: the committed files and the comment went with it
+    pub fn clear_committed(&mut self) {
+        self.files.retain(|file| !file.is_committed());
+        self.message = None;
+    }
+
+    // An empty comment removes the one set before
+    pub fn set_message(&mut self, message: &str) {
+        let message = message.trim();
+        self.message = (!message.is_empty()).then(|| message.to_string());
+    }
+}
+
+// File name shown by `status`, relative to the current directory when possible
+pub fn display_path(staged: &StagedFile) -> String {
+    std::env::current_dir()
+        .ok()
+        .and_then(|cwd| {
+            Path::new(&staged.path)
+                .strip_prefix(cwd)
+                .ok()
+                .map(|relative| relative.to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| staged.path.clone())
+}
+
+// A line of `canvas status` for the non-table output formats
+#[derive(Debug, Serialize)]
+pub struct StatusEntry {
+    pub path: String,
+    // "added" until commit uploads the file, "committed" after
+    pub state: &'static str,
+    pub file_id: Option<i64>,
+}
+
+impl StatusEntry {
+    pub fn new(staged: &StagedFile) -> StatusEntry {
+        StatusEntry {
+            path: staged.path.clone(),
+            state: if staged.is_committed() {
+                "committed"
+            } else {
+                "added"
+            },
+            file_id: staged.file_id,
+        }
+    }
+}
+
+impl Record for StatusEntry {
+    fn columns() -> Vec<Column> {
+        vec![
+            Column::new("path", "File"),
+            Column::new("state", "State"),
+            Column::id("file_id", "File ID"),
+        ]
+    }
+
+    fn values(&self) -> Vec<String> {
+        vec![
+            self.path.clone(),
+            self.state.to_string(),
+            self.file_id.map(|id| id.to_string()).unwrap_or_default(),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn staged(path: &str) -> StagedFile {
+        let (parent_path, file_name) = path.rsplit_once('/').unwrap();
+        let upload = UploadData::new(
+            serde_json::from_str("{}").unwrap(),
+            file_name.to_string(),
+            parent_path.to_string(),
+        );
+        StagedFile::new(path.to_string(), upload)
+    }
+
+    fn index(paths: &[&str]) -> StagingIndex {
+        StagingIndex {
+            files: paths.iter().map(|path| staged(path)).collect(),
+            message: None,
+        }
+    }
+
+    fn paths(index: &StagingIndex) -> Vec<&str> {
+        index.files.iter().map(|file| file.path.as_str()).collect()
+    }
+
+    #[test]
+    fn unstages_only_the_given_path() {
+        let mut index = index(&["/hw/x/a.rs", "/hw/y/a.rs"]);
+        assert!(index.unstage("/hw/y/a.rs").unwrap());
+        assert_eq!(paths(&index), ["/hw/x/a.rs"]);
+    }
+
+    #[test]
+    fn unstages_a_file_name_only_one_file_has() {
+        let mut index = index(&["/hw/x/a.rs", "/hw/y/b.rs"]);
+        assert!(index.unstage("b.rs").unwrap());
+        assert_eq!(paths(&index), ["/hw/x/a.rs"]);
+        assert!(!index.unstage("c.rs").unwrap());
+    }
+
+    #[test]
+    fn refuses_a_file_name_several_files_have() {
+        let mut index = index(&["/hw/x/a.rs", "/hw/y/a.rs"]);
+        assert!(matches!(index.unstage("a.rs"), Err(CanvasError::Usage(_))));
+        assert_eq!(paths(&index), ["/hw/x/a.rs", "/hw/y/a.rs"]);
+    }
+}
@@ -0,0 +1,441 @@
+use crate::credentials::{self, Credentials};
+use crate::data::{CommitData, UploadData};
+use crate::error::{self, CanvasError};
+use crate::staging::{StagedFile, StagingIndex};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+// Everything canvas writes lives in per-user directories following the XDG base directory spec,
+// next to $XDG_CONFIG_HOME/canvas-cli/config.json which holds the settings shared by every profile:
+//   config  $XDG_CONFIG_HOME/canvas-cli/profiles/<profile>   school url, encrypted token if there is no keyring
+//   data    $XDG_DATA_HOME/canvas-cli/profiles/<profile>     staging index
+//   cache   $XDG_CACHE_HOME/canvas-cli/profiles/<profile>    completion candidates
+const APP_DIR: &str = "canvas-cli";
+pub const DEFAULT_PROFILE: &str = "default";
+
+// Set once by run() from --profile (or $CANVAS_PROFILE), completions fall back to the same lookup
+static PROFILE: OnceLock<String> = OnceLock::new();
+
+// Settings shared by every profile, kept in $XDG_CONFIG_HOME/canvas-cli/config.json
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Settings {
+    // Profile used when neither --profile nor $CANVAS_PROFILE is given, see `canvas profile use`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_profile: Option<String>,
+}
+
+impl Settings {
+    pub fn load() -> Result<Settings, CanvasError> {
+        let path = settings_path()?;
+        match read_optional(&path)? {
+            Some(text) => error::decode(&path.to_string_lossy(), &text),
+            None => Ok(Settings::default()),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), CanvasError> {
+        write_json(&settings_path()?, self)
+    }
+}
+
+fn settings_path() -> Result<PathBuf, CanvasError> {
+    Ok(app_dir(dirs::config_dir(), "config")?.join("config.json"))
+}
+
+/*
+function: set_profile
+Description: Picks the profile every state file is read from for the rest of the run:
+    --profile / $CANVAS_PROFILE, then the default set with `canvas profile use`, then "default"
+ */
+pub fn set_profile(profile: Option<&str>) -> Result<(), CanvasError> {
+    let profile = match profile {
+        Some(profile) => profile.to_string(),
+        None => Settings::load()?
+            .default_profile
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_string()),
+    };
+    validate_profile(&profile)?;
+    PROFILE.set(profile).ok();
+    Ok(())
+}
+
+pub fn profile() -> String {
+    if let Some(profile) = PROFILE.get() {
+        return profile.clone();
+    }
+    env::var("CANVAS_PROFILE")
+        .ok()
+        .or_else(|| Settings::load().ok()?.default_profile)
+        .filter(|profile| validate_profile(profile).is_ok())
+        .unwrap_or_else(|| DEFAULT_PROFILE.to_string())
+}
+
+// Profile names become directory names, so keep them to something that can not escape profiles/
+pub fn validate_profile(profile: &str) -> Result<(), CanvasError> {
+    let valid = !profile.is_empty()
+        && !profile.starts_with('.')
+        && profile
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(CanvasError::Usage(format!(
+            "Invalid profile name {:?}, use letters, digits, '-', '_' and '.'",
+            profile
+        )))
+    }
+}
+
+fn app_dir(base: Option<PathBuf>, kind: &str) -> Result<PathBuf, CanvasError> {
+    let base = base.ok_or_else(|| {
+        CanvasError::Config(format!(
+            "Could not find the {} directory for this user",
+            kind
+        ))
+    })?;
+    Ok(base.join(APP_DIR))
+}
+
+fn profiles_dir(base: Option<PathBuf>, kind: &str) -> Result<PathBuf, CanvasError> {
+    Ok(app_dir(base, kind)?.join("profiles"))
+}
+
+// The config, data and cache directories of a profile, which may not all exist
+pub fn profile_dirs(profile: &str) -> Result<Vec<PathBuf>, CanvasError> {
+    Ok(vec![
+        profiles_dir(dirs::config_dir(), "config")?.join(profile),
+        profiles_dir(dirs::data_dir(), "data")?.join(profile),
+        profiles_dir(dirs::cache_dir(), "cache")?.join(profile),
+    ])
+}
+
+// Every profile that has a config directory, i.e. was logged in at some point, sorted by name
+pub fn list_profiles() -> Result<Vec<String>, CanvasError> {
+    let dir = profiles_dir(dirs::config_dir(), "config")?;
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(error::file_error("read", &dir)(err)),
+    };
+    let mut profiles = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            profiles.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    profiles.sort();
+    Ok(profiles)
+}
+
+pub fn profile_config_file(profile: &str, name: &str) -> Result<PathBuf, CanvasError> {
+    Ok(profiles_dir(dirs::config_dir(), "config")?
+        .join(profile)
+        .join(name))
+}
+
+pub fn config_file(name: &str) -> Result<PathBuf, CanvasError> {
+    profile_config_file(&profile(), name)
+}
+
+pub fn data_file(name: &str) -> Result<PathBuf, CanvasError> {
+    Ok(profiles_dir(dirs::data_dir(), "data")?
+        .join(profile())
+        .join(name))
+}
+
+pub fn cache_file(name: &str) -> Result<PathBuf, CanvasError> {
+    Ok(profiles_dir(dirs::cache_dir(), "cache")?
+        .join(profile())
+        .join(name))
+}
+
+// Reads a state file, a file that was never written is None
+pub fn read_optional(path: &Path) -> Result<Option<String>, CanvasError> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(Some(text)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(error::file_error("read", path)(err)),
+    }
+}
+
+// Writes a state file only the current user can read (0600), creating its directory (0700)
+pub fn write_private(path: &Path, contents: &[u8]) -> Result<(), CanvasError> {
+    if let Some(parent) = path.parent() {
+        create_private_dir(parent)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // mode() only applies to new files, so tighten ones left by older versions too
+        if path.exists() {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+                .map_err(error::file_error("write", path))?;
+        }
+    }
+    let mut file = options
+        .open(path)
+        .map_err(error::file_error("write", path))?;
+    file.write_all(contents)
+        .map_err(error::file_error("write", path))?;
+    Ok(())
+}
+
+fn create_private_dir(dir: &Path) -> Result<(), CanvasError> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder
+        .create(dir)
+        .map_err(error::file_error("create", dir))?;
+    Ok(())
+}
+
+pub fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), CanvasError> {
+    let json = serde_json::to_vec(value).map_err(|err| CanvasError::Io(err.into()))?;
+    write_private(path, &json)
+}
+
+// Where older versions kept their state, relative to the directory they were run in
+const LEGACY_ENV: &str = ".env";
+const LEGACY_UPLOAD: &str = "src/secrets/.upload_data.json";
+const LEGACY_COMMIT: &str = "src/secrets/.commit_data.json";
+
+// Whether the current directory has state of an older version for `canvas migrate` to move
+pub fn has_legacy_state() -> bool {
+    [LEGACY_ENV, LEGACY_UPLOAD]
+        .iter()
+        .any(|path| Path::new(path).exists())
+}
+
+// The old files a migration step read, and whether everything in them made it into the profile
+#[derive(Debug, Default)]
+struct Migrated {
+    files: Vec<PathBuf>,
+    complete: bool,
+}
+
+/*
+function: canva migrate
+Description: Copies the state older versions kept in the current directory into the profile's state
+    directories: the credentials from ./.env and the added (and maybe committed) file from
+    ./src/secrets. What the profile already has is never replaced. The old files are only deleted
+    when everything in them was carried over and the user agrees to it.
+ */
+pub fn migrate_legacy() -> Result<(), CanvasError> {
+    let mut migrated: Vec<Migrated> = Vec::new();
+
+    let credentials_path = credentials::credentials_path()?;
+    if Path::new(LEGACY_ENV).exists() {
+        if credentials_path.exists() {
+            println!(
+                "Profile {} is already logged in, left ./{} alone",
+                profile(),
+                LEGACY_ENV
+            );
+        } else {
+            let env = migrate_env_file(Path::new(LEGACY_ENV))?;
+            if !env.files.is_empty() {
+                println!(
+                    "Moved the credentials in ./{} to {}",
+                    LEGACY_ENV,
+                    credentials_path.display()
+                );
+            }
+            migrated.push(env);
+        }
+    }
+
+    let index_path = data_file("index.json")?;
+    if !index_path.exists() {
+        let staging = migrate_staging(Path::new("."), &index_path)?;
+        if !staging.files.is_empty() {
+            println!("Moved the added file to {}", index_path.display());
+        }
+        migrated.push(staging);
+    }
+
+    let moved: Vec<&PathBuf> = migrated.iter().flat_map(|m| &m.files).collect();
+    if moved.is_empty() {
+        println!("Nothing to migrate in this directory");
+        return Ok(());
+    }
+    let incomplete: Vec<String> = migrated
+        .iter()
+        .filter(|m| !m.complete)
+        .flat_map(|m| &m.files)
+        .map(|path| format!("./{}", path.display()))
+        .collect();
+    if !incomplete.is_empty() {
+        println!(
+            "Could not carry over everything in {}, left the old files in place",
+            incomplete.join(", ")
+        );
+        return Ok(());
+    }
+    let names: Vec<String> = moved
+        .iter()
+        .map(|path| format!("./{}", path.display()))
+        .collect();
+    print!("Delete {}? [y/N] ", names.join(", "));
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    if matches!(answer.trim(), "y" | "Y" | "yes") {
+        for path in moved {
+            fs::remove_file(path).map_err(error::file_error("delete", path))?;
+        }
+    } else {
+        println!("Left them in place, they are not used anymore");
+    }
+    Ok(())
+}
+
+// Saves the credentials in an old .env file for the profile. Complete when it had both the school
+// url and the token, older versions wrote them together.
+fn migrate_env_file(env_file: &Path) -> Result<Migrated, CanvasError> {
+    let text = match read_optional(env_file)? {
+        Some(text) => text,
+        None => return Ok(Migrated::default()),
+    };
+    let mut credentials = Credentials::default();
+    let mut token = None;
+    for (key, value) in text.lines().filter_map(|line| line.split_once('=')) {
+        let value = value.trim().trim_matches('"').to_string();
+        match key.trim() {
+            "SCHOOL_BASE_URL" => credentials.school_base_url = Some(value),
+            "CANVAS_AUTH_TOKEN" => token = Some(value),
+            _ => (),
+        }
+    }
+    if credentials.school_base_url.is_none() && token.is_none() {
+        return Ok(Migrated::default());
+    }
+    let complete = credentials.school_base_url.is_some() && token.is_some();
+    if let Some(token) = token {
+        credentials.token_store = Some(credentials::save_token(&token)?);
+    }
+    credentials.save()?;
+    Ok(Migrated {
+        files: vec![env_file.to_path_buf()],
+        complete,
+    })
+}
+
+// Older versions wrote the Result of the commit request to .commit_data.json, {"Ok": {...}} or
+// {"Err": "..."}. A bare file is accepted too.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LegacyCommit {
+    Result(Result<CommitData, String>),
+    Bare(CommitData),
+}
+
+/*
+function: migrate_staging
+Description: Stages the single file older versions added (.upload_data.json) in the index at
+    `index_path`, with its Canvas file id when .commit_data.json holds a successful commit.
+    The legacy paths are relative to `dir`. Incomplete when the commit failed or had no file id.
+ */
+fn migrate_staging(dir: &Path, index_path: &Path) -> Result<Migrated, CanvasError> {
+    let legacy_upload = dir.join(LEGACY_UPLOAD);
+    let legacy_commit = dir.join(LEGACY_COMMIT);
+
+    let text = match read_optional(&legacy_upload)? {
+        Some(text) => text,
+        None => return Ok(Migrated::default()),
+    };
+    let mut migrated = Migrated {
+        files: vec![PathBuf::from(LEGACY_UPLOAD)],
+        complete: true,
+    };
+    let upload: UploadData = error::decode(&legacy_upload.to_string_lossy(), &text)?;
+    let path = Path::new(&upload.parent_path).join(&upload.file_name);
+    let mut staged = StagedFile::new(path.to_string_lossy().into_owned(), upload);
+    if let Some(text) = read_optional(&legacy_commit)? {
+        let commit: LegacyCommit = error::decode(&legacy_commit.to_string_lossy(), &text)?;
+        staged.file_id = match commit {
+            LegacyCommit::Result(Ok(commit)) | LegacyCommit::Bare(commit) => commit.id,
+            LegacyCommit::Result(Err(_)) => None,
+        };
+        migrated.complete = staged.file_id.is_some();
+        migrated.files.push(PathBuf::from(LEGACY_COMMIT));
+    }
+    let mut index = StagingIndex::default();
+    index.stage(staged);
+    write_json(index_path, &index)?;
+    Ok(migrated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // What older versions left in src/secrets after `add` and `commit`
+    const UPLOAD: &str = r#"{"file_data":{"file_param":"file","progress":null,"upload_url":"https://canvas.test/files_api","upload_params":{"filename":"main.rs","content_type":"text/x-rust"}},"file_name":"main.rs","parent_path":"/home/student/hw1"}"#;
+    const COMMITTED: &str = r#"{"Ok":{"location":null,"instfs_uuid":null,"id":4242,"uuid":"abc","folder_id":7,"display_name":"main.rs","filename":"main.rs","upload_status":"success","content-type":"text/x-rust","url":null,"size":120,"created_at":null,"updated_at":null,"unlock_at":null,"locked":false,"hidden":false,"lock_at":null,"hidden_for_user":false,"thumbnail_url":null,"modified_at":null,"mime_class":"code","media_entry_id":null,"category":"uncategorized","locked_for_user":false,"preview_url":null}}"#;
+
+    // A directory with src/secrets holding `upload` and, if given, `commit`
+    fn legacy_dir(name: &str, commit: Option<&str>) -> PathBuf {
+        let dir = env::temp_dir().join(format!("canvas-cli-test-{}-{}", name, std::process::id()));
+        fs::create_dir_all(dir.join("src/secrets")).unwrap();
+        fs::write(dir.join(LEGACY_UPLOAD), UPLOAD).unwrap();
+        if let Some(commit) = commit {
+            fs::write(dir.join(LEGACY_COMMIT), commit).unwrap();
+        }
+        dir
+    }
+
+    fn migrated_index(dir: &Path) -> (Migrated, StagingIndex) {
+        let index_path = dir.join("index.json");
+        let migrated = migrate_staging(dir, &index_path).unwrap();
+        let index = error::decode("index.json", &fs::read_to_string(&index_path).unwrap()).unwrap();
+        (migrated, index)
+    }
+
+    #[test]
+    fn carries_over_a_committed_file() {
+        let dir = legacy_dir("committed", Some(COMMITTED));
+        let (migrated, index) = migrated_index(&dir);
+        fs::remove_dir_all(&dir).ok();
+
+        assert!(migrated.complete);
+        assert_eq!(migrated.files.len(), 2);
+        assert_eq!(index.files.len(), 1);
+        assert_eq!(index.files[0].path, "/home/student/hw1/main.rs");
+        assert_eq!(index.files[0].file_id, Some(4242));
+    }
+
+    #[test]
+    fn carries_over_an_added_file() {
+        let dir = legacy_dir("added", None);
+        let (migrated, index) = migrated_index(&dir);
+        fs::remove_dir_all(&dir).ok();
+
+        assert!(migrated.complete);
+        assert_eq!(migrated.files, vec![PathBuf::from(LEGACY_UPLOAD)]);
+        assert_eq!(index.files[0].file_id, None);
+    }
+
+    #[test]
+    fn a_failed_commit_is_incomplete() {
+        let dir = legacy_dir("failed", Some(r#"{"Err":"Error getting account info"}"#));
+        let (migrated, index) = migrated_index(&dir);
+        fs::remove_dir_all(&dir).ok();
+
+        assert!(!migrated.complete);
+        assert_eq!(index.files[0].file_id, None);
+    }
+}
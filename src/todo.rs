@@ -0,0 +1,302 @@
+use crate::cli::OutputFormat;
+use crate::client::BlockingCanvasClient;
+use crate::data::{Assignment, Course, PlannerItem, PlannerSubmissions, TodoEntry};
+use crate::dates;
+use crate::error::CanvasError;
+use crate::output::{self, Column, Record};
+use chrono::{DateTime, Duration, FixedOffset, Local, Utc};
+use colored::Colorize;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+// `canvas todo` lists what is due soon across every active course. The planner has the dated items of
+// every course, the to do list adds what Canvas thinks still needs a submission; the two overlap.
+
+// How far back unsubmitted items are still listed as overdue
+const OVERDUE_DAYS: i64 = 28;
+
+#[derive(Debug, Serialize)]
+pub struct TodoItem {
+    // assignment, quiz or discussion
+    pub kind: String,
+    pub title: String,
+    pub course: String,
+    pub course_id: Option<i64>,
+    pub id: i64,
+    // RFC 3339, None for undated items on the to do list
+    pub due_at: Option<String>,
+    #[serde(skip)]
+    pub due_date: Option<DateTime<FixedOffset>>,
+    // Always relative, whatever --date-format says
+    pub due: String,
+    pub overdue: bool,
+    pub url: Option<String>,
+}
+
+impl Record for TodoItem {
+    fn columns() -> Vec<Column> {
+        vec![
+            Column::new("due_at", "Due").alias(&["due"]),
+            Column::new("title", "Title").alias(&["name"]),
+            Column::new("kind", "Type").alias(&["type"]),
+            Column::new("course", "Course"),
+            Column::id("id", "ID"),
+            Column::new("course_id", "Course ID"),
+        ]
+    }
+
+    fn values(&self) -> Vec<String> {
+        self.row(self.due_at.clone().unwrap_or_default())
+    }
+
+    fn table_values(&self) -> Vec<String> {
+        self.row(self.due.clone())
+    }
+}
+
+// Time zones of the courses by id, only filled in with --timezone course
+type CourseZones = HashMap<i64, String>;
+
+impl TodoItem {
+    fn new(
+        kind: &str,
+        id: i64,
+        title: String,
+        course: (Option<i64>, Option<String>),
+        due_at: Option<&str>,
+        url: Option<String>,
+        zones: &CourseZones,
+    ) -> TodoItem {
+        let course_zone = course.0.and_then(|course_id| zones.get(&course_id));
+        let due_date = due_at
+            .and_then(|due_at| dates::parse_reported("due_at", due_at, Some(&title)))
+            .map(|due_date| dates::localize(due_date, course_zone.map(String::as_str)));
+        TodoItem {
+            kind: kind.to_string(),
+            title,
+            course: course.1.unwrap_or_default(),
+            course_id: course.0,
+            id,
+            due_at: due_date.map(|due_date| due_date.to_rfc3339()),
+            due_date,
+            due: due_date.map(dates::relative).unwrap_or_default(),
+            overdue: due_date.is_some_and(|due_date| due_date < Utc::now()),
+            url,
+        }
+    }
+
+    fn row(&self, due: String) -> Vec<String> {
+        vec![
+            due,
+            self.title.clone(),
+            self.kind.clone(),
+            self.course.clone(),
+            self.id.to_string(),
+            self.course_id
+                .map(|course_id| course_id.to_string())
+                .unwrap_or_default(),
+        ]
+    }
+
+    // Planner items that are done, take no submission or are not an assignment, quiz or discussion are None
+    fn from_planner(item: PlannerItem, zones: &CourseZones) -> Option<TodoItem> {
+        let kind = match item.plannable_type.as_deref()? {
+            "assignment" => "assignment",
+            "quiz" => "quiz",
+            "discussion_topic" => "discussion",
+            _ => return None,
+        };
+        if item
+            .planner_override
+            .as_ref()
+            .is_some_and(|planner_override| planner_override.marked_complete)
+        {
+            return None;
+        }
+        let done = match &item.submissions {
+            Some(PlannerSubmissions::Status(status)) => status.submitted || status.excused,
+            _ => false,
+        };
+        let plannable = item.plannable?;
+        let due_at = plannable.due_at.or(item.plannable_date);
+        let todo = TodoItem::new(
+            kind,
+            item.plannable_id?,
+            plannable.title?,
+            (item.course_id, item.context_name),
+            due_at.as_deref(),
+            item.html_url,
+            zones,
+        );
+        // Past items are only listed while they still need a submission
+        let expects_submission = matches!(item.submissions, Some(PlannerSubmissions::Status(_)));
+        if done || (todo.overdue && !expects_submission) {
+            return None;
+        }
+        Some(todo)
+    }
+
+    // Only entries waiting for our submission, not those waiting for us to grade them
+    fn from_todo(entry: TodoEntry, zones: &CourseZones) -> Option<TodoItem> {
+        if entry.kind.as_deref() != Some("submitting") {
+            return None;
+        }
+        let course = (entry.course_id, entry.context_name);
+        if let Some(quiz) = entry.quiz {
+            let due_at = quiz.due_at.as_deref();
+            return Some(TodoItem::new(
+                "quiz",
+                quiz.id?,
+                quiz.title?,
+                course,
+                due_at,
+                entry.html_url,
+                zones,
+            ));
+        }
+        let assignment = entry.assignment?;
+        let (kind, id) = planner_identity(&assignment)?;
+        let due_at = assignment.due_at.as_deref();
+        Some(TodoItem::new(
+            kind,
+            id,
+            assignment.name.clone()?,
+            course,
+            due_at,
+            entry.html_url,
+            zones,
+        ))
+    }
+}
+
+// The type and id the planner lists an assignment under: quizzes and graded discussions by their own id
+fn planner_identity(assignment: &Assignment) -> Option<(&'static str, i64)> {
+    if let Some(quiz_id) = assignment.quiz_id {
+        return Some(("quiz", quiz_id));
+    }
+    let topic_id = assignment
+        .discussion_topic
+        .as_ref()
+        .and_then(|topic| topic.get("id"))
+        .and_then(|id| id.as_i64());
+    match topic_id {
+        Some(topic_id) => Some(("discussion", topic_id)),
+        None => Some(("assignment", assignment.id?)),
+    }
+}
+
+/*
+function: fetch_todo
+Description: Fetches the planner from OVERDUE_DAYS ago until `days` from now together with the to do
+    list, and merges them: overdue first, then by due date, undated last. One of the two failing only
+    prints a warning.
+Return: Result<Vec<TodoItem>, CanvasError>
+ */
+pub fn fetch_todo(canvas: &BlockingCanvasClient, days: u32) -> Result<Vec<TodoItem>, CanvasError> {
+    let now = Utc::now();
+    let today = Local::now().date_naive();
+    let planner_path = format!(
+        "/api/v1/planner/items?per_page=100&start_date={}&end_date={}",
+        today - Duration::days(OVERDUE_DAYS),
+        today + Duration::days(days as i64 + 1)
+    );
+    // Only --timezone course needs the courses, for their time zones
+    let zones: CourseZones = if dates::uses_course_zone() {
+        canvas
+            .get_paginated::<Course>("/api/v1/courses?per_page=100", None)?
+            .into_iter()
+            .filter_map(|course| Some((course.id, course.time_zone?)))
+            .collect()
+    } else {
+        HashMap::new()
+    };
+    let client = canvas.client();
+    let (planner, todo) = canvas.block_on(async {
+        futures::join!(
+            client.get_paginated::<PlannerItem>(&planner_path, None),
+            client.get_paginated::<TodoEntry>("/api/v1/users/self/todo?per_page=100", None)
+        )
+    });
+    let (planner, todo) = match (planner, todo) {
+        (Err(err), Err(_)) => return Err(err),
+        (planner, todo) => (
+            skip_failed(planner, "planner"),
+            skip_failed(todo, "to do list"),
+        ),
+    };
+
+    let until = now + Duration::days(days as i64);
+    let mut seen = HashSet::new();
+    let mut items: Vec<TodoItem> = planner
+        .into_iter()
+        .filter_map(|item| TodoItem::from_planner(item, &zones))
+        .chain(
+            todo.into_iter()
+                .filter_map(|entry| TodoItem::from_todo(entry, &zones)),
+        )
+        .filter(|item| item.due_date.is_none_or(|due_date| due_date <= until))
+        .filter(|item| seen.insert((item.kind.clone(), item.id)))
+        .collect();
+    for item in &mut items {
+        // Canvas sends the page of an item relative to the school url
+        item.url = item.url.as_deref().map(|url| client.url(url));
+    }
+    items.sort_by_key(|item| (item.due_date.is_none(), item.due_date));
+    Ok(items)
+}
+
+fn skip_failed<T>(result: Result<Vec<T>, CanvasError>, source: &str) -> Vec<T> {
+    result.unwrap_or_else(|err| {
+        eprintln!("{} {}: {}", "Could not fetch the".yellow(), source, err);
+        Vec::new()
+    })
+}
+
+/*
+function: print_todo
+Description: The table is grouped by day, overdue items first under their own heading.
+    Every other format gets one flat list.
+ */
+pub fn print_todo(items: Vec<TodoItem>, output: OutputFormat) -> Result<(), CanvasError> {
+    if output != OutputFormat::Table {
+        return output::render(&items, output);
+    }
+    if items.is_empty() {
+        println!("Nothing due, enjoy the break");
+        return Ok(());
+    }
+    let mut groups: Vec<(String, Vec<TodoItem>)> = Vec::new();
+    for item in items {
+        let heading = if item.overdue {
+            "Overdue".red().bold().to_string()
+        } else {
+            day_heading(item.due_date.as_ref()).bold().to_string()
+        };
+        match groups.last_mut() {
+            Some((last, group)) if *last == heading => group.push(item),
+            _ => groups.push((heading, vec![item])),
+        }
+    }
+    for (index, (heading, group)) in groups.iter().enumerate() {
+        if index > 0 {
+            output::heading("")?;
+        }
+        output::heading(heading)?;
+        output::render(group, output)?;
+    }
+    Ok(())
+}
+
+// The day a localized time falls on, seen from the same time zone
+fn day_heading(due_date: Option<&DateTime<FixedOffset>>) -> String {
+    let due_date = match due_date {
+        Some(due_date) => due_date,
+        None => return "No due date".to_string(),
+    };
+    let today = dates::today_in(due_date);
+    match due_date.date_naive() {
+        day if day == today => "Today".to_string(),
+        day if day == today + Duration::days(1) => "Tomorrow".to_string(),
+        day => day.format("%A %m-%d-%Y").to_string(),
+    }
+}
@@ -0,0 +1,361 @@
+use crate::cli::AssignmentFilter;
+use crate::client::BlockingCanvasClient;
+use crate::data::{ValidAssignment, ValidCourse};
+use crate::error::CanvasError;
+use crate::preflight;
+use crate::resolve::{self, Inexact};
+use colored::Colorize;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::{Confirm, FuzzySelect, Input, MultiSelect};
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+
+// `canvas submit -i` walks through a submission: pick a course, one of its assignments that takes
+// uploads, the files and a comment, then confirm and push them like `canvas push` does. In a terminal
+// the lists can be searched by typing; without one (e.g. piped input) every question becomes a
+// numbered prompt.
+
+// The file picker does not look deeper than this below the current directory, or at more files
+const MAX_DEPTH: usize = 4;
+const MAX_FILES: usize = 500;
+// Directories no one submits from
+const SKIPPED_DIRS: [&str; 3] = ["target", "node_modules", "__pycache__"];
+
+enum Prompter {
+    Terminal,
+    Plain,
+}
+
+impl Prompter {
+    fn new() -> Prompter {
+        if io::stdin().is_terminal() && io::stderr().is_terminal() {
+            Prompter::Terminal
+        } else {
+            Prompter::Plain
+        }
+    }
+
+    fn select(&self, prompt: &str, items: &[String]) -> Result<usize, CanvasError> {
+        match self {
+            Prompter::Terminal => FuzzySelect::with_theme(&ColorfulTheme::default())
+                .with_prompt(prompt)
+                .items(items)
+                .default(0)
+                .interact_opt()
+                .map_err(dialog_error)?
+                .ok_or_else(cancelled),
+            Prompter::Plain => {
+                list(prompt, items);
+                loop {
+                    let answer = read_answer(&format!("Pick one [1-{}]", items.len()))?;
+                    match parse_number(&answer, items.len()) {
+                        Some(index) => return Ok(index),
+                        None => eprintln!("Answer with a number between 1 and {}", items.len()),
+                    }
+                }
+            }
+        }
+    }
+
+    fn select_many(&self, prompt: &str, items: &[String]) -> Result<Vec<usize>, CanvasError> {
+        match self {
+            Prompter::Terminal => {
+                let picked = MultiSelect::with_theme(&ColorfulTheme::default())
+                    .with_prompt(format!("{} (space to pick, enter when done)", prompt))
+                    .items(items)
+                    .interact_opt()
+                    .map_err(dialog_error)?
+                    .ok_or_else(cancelled)?;
+                Ok(picked)
+            }
+            Prompter::Plain => {
+                list(prompt, items);
+                loop {
+                    let answer = read_answer(&format!(
+                        "Pick one or more, separated by commas [1-{}]",
+                        items.len()
+                    ))?;
+                    let picked: Option<Vec<usize>> = answer
+                        .split([',', ' '])
+                        .filter(|part| !part.is_empty())
+                        .map(|part| parse_number(part, items.len()))
+                        .collect();
+                    match picked {
+                        Some(picked) if !picked.is_empty() => return Ok(picked),
+                        _ => eprintln!("Answer with numbers between 1 and {}", items.len()),
+                    }
+                }
+            }
+        }
+    }
+
+    // A line of text, possibly empty
+    fn input(&self, prompt: &str) -> Result<String, CanvasError> {
+        match self {
+            Prompter::Terminal => Input::<String>::with_theme(&ColorfulTheme::default())
+                .with_prompt(prompt)
+                .allow_empty(true)
+                .interact_text()
+                .map_err(dialog_error),
+            Prompter::Plain => read_answer(&format!("{}:", prompt)),
+        }
+    }
+
+    fn confirm(&self, prompt: &str) -> Result<bool, CanvasError> {
+        match self {
+            Prompter::Terminal => Ok(Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt(prompt)
+                .default(false)
+                .interact_opt()
+                .map_err(dialog_error)?
+                .unwrap_or(false)),
+            Prompter::Plain => {
+                let answer = read_answer(&format!("{} [y/N]", prompt))?;
+                Ok(matches!(answer.to_lowercase().as_str(), "y" | "yes"))
+            }
+        }
+    }
+}
+
+fn dialog_error(err: dialoguer::Error) -> CanvasError {
+    match err {
+        dialoguer::Error::IO(err) => CanvasError::Io(err),
+    }
+}
+
+fn cancelled() -> CanvasError {
+    CanvasError::Usage("Cancelled, nothing was submitted".to_string())
+}
+
+// Numbered prompts go to stderr, so nothing but the results ends up in piped output
+fn list(prompt: &str, items: &[String]) {
+    eprintln!("{}", prompt.bold());
+    for (number, item) in items.iter().enumerate() {
+        eprintln!("  {:>3}) {}", number + 1, item);
+    }
+}
+
+fn read_answer(prompt: &str) -> Result<String, CanvasError> {
+    eprint!("{} ", prompt);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer)? == 0 {
+        return Err(cancelled());
+    }
+    Ok(answer.trim().to_string())
+}
+
+// A 1-based answer as an index into `len` items
+fn parse_number(answer: &str, len: usize) -> Option<usize> {
+    answer
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .filter(|number| (1..=len).contains(number))
+        .map(|number| number - 1)
+}
+
+/*
+function: canva submit -i [<course>] [<assignment>]
+Description: Asks for whatever was not given on the command line, shows a summary and only pushes
+    the picked files once it is confirmed
+ */
+pub fn run(
+    canvas: &BlockingCanvasClient,
+    course: Option<&str>,
+    assignment: Option<&str>,
+    force: bool,
+) -> Result<(), CanvasError> {
+    let prompter = Prompter::new();
+    let course = match course {
+        // An inexact match is only named here, the summary has to be confirmed anyway
+        Some(course) => resolve::course(canvas, course, Inexact::Note)?,
+        None => pick_course(canvas, &prompter)?,
+    };
+    let assignment = pick_assignment(canvas, &prompter, &course, assignment)?;
+    let files = pick_files(&prompter, &assignment)?;
+    preflight::check(canvas, course.id, assignment.id, &files, force)?;
+
+    let comment = prompter.input("Comment for the grader (optional)")?;
+    let comment = comment.trim();
+    eprintln!();
+    eprintln!(
+        "{:<11} {} {}",
+        "Course:".bold(),
+        course.course_code,
+        course.name
+    );
+    eprintln!("{:<11} {}", "Assignment:".bold(), describe(&assignment));
+    eprintln!("{:<11} {}", "Files:".bold(), files.join(", "));
+    if !comment.is_empty() {
+        eprintln!("{:<11} {}", "Comment:".bold(), comment);
+    }
+    if !prompter.confirm("Submit?")? {
+        return Err(cancelled());
+    }
+
+    // Like `canvas push`, only the files picked here are submitted and the rest of the index is left alone
+    let comment = Some(comment).filter(|comment| !comment.is_empty());
+    crate::push_checked(canvas, &course.id, &assignment.id, &files, comment)
+}
+
+fn pick_course(
+    canvas: &BlockingCanvasClient,
+    prompter: &Prompter,
+) -> Result<ValidCourse, CanvasError> {
+    let mut courses = crate::fetch_courses(canvas)?;
+    if courses.is_empty() {
+        return Err(CanvasError::NotFound(
+            "You are not enrolled in any course".to_string(),
+        ));
+    }
+    let items: Vec<String> = courses
+        .iter()
+        .map(|course| format!("{} {}", course.course_code, course.name))
+        .collect();
+    let index = prompter.select("Course", &items)?;
+    Ok(courses.swap_remove(index))
+}
+
+// Only assignments that take file uploads and are not locked can be submitted to here
+fn pick_assignment(
+    canvas: &BlockingCanvasClient,
+    prompter: &Prompter,
+    course: &ValidCourse,
+    query: Option<&str>,
+) -> Result<ValidAssignment, CanvasError> {
+    let assignments = crate::fetch_assignments(canvas, &course.id, &AssignmentFilter::default())?;
+    if let Some(query) = query {
+        let id = resolve::assignment_id(canvas, course.id, query, Inexact::Note)?;
+        return assignments
+            .into_iter()
+            .find(|assignment| assignment.id == id)
+            .ok_or_else(|| {
+                CanvasError::NotFound(format!(
+                    "Assignment {} not found in course {}",
+                    id, course.id
+                ))
+            });
+    }
+    let mut open: Vec<ValidAssignment> = assignments
+        .into_iter()
+        .filter(|assignment| {
+            !assignment.locked_for_user
+                && assignment
+                    .submission_types
+                    .iter()
+                    .any(|kind| kind == "online_upload")
+        })
+        .collect();
+    if open.is_empty() {
+        return Err(CanvasError::NotFound(format!(
+            "{} has no open assignments that take file uploads",
+            course.course_code
+        )));
+    }
+    let items: Vec<String> = open.iter().map(describe).collect();
+    let index = prompter.select("Assignment", &items)?;
+    Ok(open.swap_remove(index))
+}
+
+fn describe(assignment: &ValidAssignment) -> String {
+    let mut description = assignment.name.clone();
+    if let Some(due) = assignment.due() {
+        description.push_str(&format!(", due {}", due));
+    }
+    if assignment.submitted {
+        description.push_str(", already submitted");
+    }
+    description
+}
+
+// Files below the current directory, only those with an extension the assignment accepts
+fn pick_files(
+    prompter: &Prompter,
+    assignment: &ValidAssignment,
+) -> Result<Vec<String>, CanvasError> {
+    let mut files = Vec::new();
+    let cut = collect_files(
+        Path::new("."),
+        0,
+        &assignment.allowed_extensions,
+        &mut files,
+    )?;
+    files.sort();
+    if cut {
+        eprintln!(
+            "{}",
+            format!(
+                "Only the first {} files are listed, run `canvas submit -i` from a smaller directory \
+                 to see the others",
+                MAX_FILES
+            )
+            .yellow()
+        );
+    }
+    if files.is_empty() {
+        let accepted = if assignment.allowed_extensions.is_empty() {
+            String::new()
+        } else {
+            format!(" ending in .{}", assignment.allowed_extensions.join(", ."))
+        };
+        return Err(CanvasError::NotFound(format!(
+            "No files{} found below the current directory",
+            accepted
+        )));
+    }
+    let picked = loop {
+        let picked = prompter.select_many("Files to submit", &files)?;
+        if !picked.is_empty() {
+            break picked;
+        }
+        eprintln!("Pick at least one file");
+    };
+    Ok(picked
+        .into_iter()
+        .map(|index| files[index].clone())
+        .collect())
+}
+
+// Walks `dir` in name order, so the same files are listed every time. Subdirectories that can not
+// be read are skipped. Returns whether the walk stopped at MAX_FILES.
+fn collect_files(
+    dir: &Path,
+    depth: usize,
+    extensions: &[String],
+    files: &mut Vec<String>,
+) -> Result<bool, CanvasError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if depth == 0 => return Err(err.into()),
+        Err(_) => return Ok(false),
+    };
+    let mut entries: Vec<fs::DirEntry> = entries.filter_map(Result::ok).collect();
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            if depth + 1 < MAX_DEPTH
+                && !SKIPPED_DIRS.contains(&name.as_str())
+                && collect_files(&path, depth + 1, extensions, files)?
+            {
+                return Ok(true);
+            }
+        } else if file_type.is_file() && preflight::accepts(&path, extensions) {
+            if files.len() == MAX_FILES {
+                return Ok(true);
+            }
+            let relative = path.strip_prefix(".").unwrap_or(&path);
+            files.push(relative.to_string_lossy().into_owned());
+        }
+    }
+    Ok(false)
+}